@@ -0,0 +1,17 @@
+#!/bin/sh
+# Builds assets/art/PigAnimations/pig_sheet.png (+ .meta) for the Money tower from the
+# source clips. Needs ffmpeg on the PATH. Rerun whenever the clips change.
+set -eu
+
+cd "$(dirname "$0")/.."
+clips=assets/art/PigAnimations
+frames=$(mktemp -d)
+trap 'rm -rf "$frames"' EXIT
+
+for clip in Woobling DerpyCoin; do
+    mkdir -p "$frames/$clip"
+    ffmpeg -loglevel error -i "$clips/$clip.mp4" -vf fps=24 "$frames/$clip/%04d.png"
+done
+
+cargo run --release --bin pack_frames -- "$clips/pig_sheet.png" \
+    Woobling="$frames/Woobling" DerpyCoin="$frames/DerpyCoin" --fps 24 --frame-width 400
@@ -0,0 +1,234 @@
+use std::{env, fs};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{GameState, save::{self, MetaSave}, sim::{GameplaySet, SimSet}};
+
+/// Bump whenever the tower, enemy, wave or reward tables change, since replays recorded
+/// against other numbers won't play back the same.
+pub const CONTENT_VERSION: u32 = 2;
+const REPLAY_FILE: &str = "replay.json";
+
+/// Everything the player can do that changes the game. Input systems only queue these;
+/// handlers apply them on a simulation tick so a replay can feed them back at the same ticks.
+#[derive(Event, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum PlayerAction {
+    ChooseNode(usize),
+    PickReward(usize),
+    /// Index into `SelectableTowers::possible_towers`.
+    SelectTower(usize),
+    PlaceTower { cell: (i32, i32) },
+    CollectCoin { position: [f32; 2] },
+}
+
+/// Actions from live input, waiting for the next tick.
+#[derive(Resource, Default)]
+pub struct ActionQueue(pub Vec<PlayerAction>);
+
+impl ActionQueue {
+    pub fn push(&mut self, action: PlayerAction) {
+        self.0.push(action);
+    }
+}
+
+/// Fixed-timestep simulation ticks since launch.
+#[derive(Resource, Default)]
+pub struct SimTick(pub u64);
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedAction {
+    pub tick: u64,
+    pub action: PlayerAction,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Replay {
+    pub content_version: u32,
+    pub seed: u64,
+    /// Ticks per second; actions are recorded by tick, so playback has to use the same rate.
+    pub tick_rate: f64,
+    /// Progress the run was started with, since it decides the loadout and reward pool.
+    pub meta: MetaSave,
+    pub actions: Vec<RecordedAction>,
+}
+
+#[derive(Resource)]
+pub enum ReplayMode {
+    /// Not recording, e.g. because the run was resumed from a save and can't be replayed from its seed.
+    Off,
+    Recording(Replay),
+    Playing { replay: Replay, next: usize },
+}
+
+impl ReplayMode {
+    pub fn is_playing(&self) -> bool {
+        matches!(self, ReplayMode::Playing { .. })
+    }
+}
+
+pub fn not_replaying(mode: Res<ReplayMode>) -> bool {
+    !mode.is_playing()
+}
+
+/// Reads `--replay <file>` from the command line.
+pub fn replay_from_args() -> Option<Replay> {
+    let args: Vec<String> = env::args().collect();
+    let path = args.iter().position(|arg| arg == "--replay").and_then(|i| args.get(i + 1))?;
+    let replay = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| serde_json::from_str::<Replay>(&contents).map_err(|err| err.to_string()));
+    match replay {
+        Ok(replay) => {
+            if replay.content_version != CONTENT_VERSION {
+                warn!("{path} was recorded on content version {}, it may not play back the same", replay.content_version);
+            }
+            Some(replay)
+        }
+        Err(err) => {
+            error!("could not read replay {path}: {err}");
+            None
+        }
+    }
+}
+
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ActionSet {
+    /// Advances the tick and sends this tick's `PlayerAction`s.
+    Dispatch,
+    /// Systems reading `PlayerAction`s.
+    Handle,
+}
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<PlayerAction>()
+            .init_resource::<ActionQueue>()
+            .init_resource::<SimTick>()
+            .configure_sets(FixedUpdate, (ActionSet::Dispatch, ActionSet::Handle)
+                .chain()
+                .in_set(SimSet::Gameplay)
+                .before(GameplaySet::Act))
+            .add_systems(FixedUpdate, (advance_tick, dispatch_actions).chain().in_set(ActionSet::Dispatch))
+            // Screens change on a tick boundary too, so a replay enters each battle on the same tick.
+            .add_systems(FixedUpdate, (apply_deferred, apply_state_transition::<GameState>).chain().after(SimSet::End))
+            .add_systems(OnEnter(GameState::GameOver), write_replay)
+            .add_systems(OnEnter(GameState::Victory), write_replay)
+            .add_systems(Last, write_replay.run_if(on_event::<AppExit>()));
+    }
+}
+
+fn advance_tick(mut tick: ResMut<SimTick>) {
+    tick.0 += 1;
+}
+
+fn dispatch_actions(
+    tick: Res<SimTick>,
+    mut queue: ResMut<ActionQueue>,
+    mut mode: ResMut<ReplayMode>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    match &mut *mode {
+        ReplayMode::Off => actions.send_batch(queue.0.drain(..)),
+        ReplayMode::Recording(replay) => {
+            for action in queue.0.drain(..) {
+                replay.actions.push(RecordedAction { tick: tick.0, action });
+                actions.send(action);
+            }
+        }
+        ReplayMode::Playing { replay, next } => {
+            // Live input is ignored while a replay plays.
+            queue.0.clear();
+            while let Some(recorded) = replay.actions.get(*next).filter(|recorded| recorded.tick <= tick.0) {
+                actions.send(recorded.action);
+                *next += 1;
+            }
+        }
+    }
+}
+
+fn write_replay(mode: Res<ReplayMode>) {
+    if let ReplayMode::Recording(replay) = &*mode {
+        save::write_json(REPLAY_FILE, replay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::{
+        enemies::{Enemies, EnemyArchetype, EnemyHealth, EnemyState},
+        rng::RunRng,
+        sim,
+        testing::{battle_app, with_data_dir, SEED},
+        towers::{Money, PlacedTower},
+    };
+
+    const TICKS: u64 = 1200;
+
+    #[derive(PartialEq, Debug)]
+    struct Snapshot {
+        tick: u64,
+        money: u32,
+        next_roll: u64,
+        towers: usize,
+        enemies: Vec<(Enemies, Vec3, i32, EnemyState)>,
+    }
+
+    fn snapshot(app: &mut App) -> Snapshot {
+        let enemies = app.world
+            .query::<(&EnemyArchetype, &Transform, &EnemyHealth, &EnemyState)>()
+            .iter(&app.world)
+            .map(|(archetype, transform, health, state)| (archetype.0, transform.translation, health.health, *state))
+            .collect();
+        Snapshot {
+            tick: app.world.resource::<SimTick>().0,
+            money: app.world.resource::<Money>().0,
+            next_roll: app.world.resource::<RunRng>().rng.clone().gen(),
+            towers: app.world.query::<&PlacedTower>().iter(&app.world).count(),
+            enemies,
+        }
+    }
+
+    #[test]
+    fn replay_plays_back_the_recorded_battle() {
+        // Recording writes the replay if the battle is lost, which must not land in the real data dir.
+        with_data_dir("replay", |_| play_back_recorded_battle());
+    }
+
+    fn play_back_recorded_battle() {
+        let mut recording = battle_app(ReplayMode::Recording(Replay {
+            content_version: CONTENT_VERSION,
+            seed: SEED,
+            tick_rate: sim::DEFAULT_TICK_RATE,
+            meta: MetaSave::default(),
+            actions: Vec::new(),
+        }));
+        for update in 0..TICKS {
+            if update == 30 || update == 90 {
+                let mut queue = recording.world.resource_mut::<ActionQueue>();
+                queue.push(PlayerAction::SelectTower(0));
+                queue.push(PlayerAction::PlaceTower { cell: (-7, (update / 30) as i32 - 2) });
+            }
+            recording.update();
+        }
+        let recorded = snapshot(&mut recording);
+        assert_eq!(recorded.towers, 2);
+        assert!(!recorded.enemies.is_empty());
+        let Some(ReplayMode::Recording(replay)) = recording.world.remove_resource::<ReplayMode>() else {
+            panic!("recording stopped");
+        };
+        assert_eq!(replay.actions.len(), 4);
+
+        let mut playback = battle_app(ReplayMode::Playing { replay, next: 0 });
+        for _ in 0..TICKS {
+            playback.update();
+        }
+        assert_eq!(snapshot(&mut playback), recorded);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::{asset::FileAssetIo, prelude::*, utils::HashMap};
+
+pub const TILESHEET_PATH: &str = "kenney/Tilesheet/towerDefense_tilesheet.png";
+const TILESHEET_COLUMNS: usize = 23;
+const TILESHEET_ROWS: usize = 13;
+const TILE_SIZE: f32 = 64.0;
+
+/// Explosion frames on the Kenney tilesheet, shared by every die clip.
+pub const EXPLOSION_FRAMES: [usize; 3] = [19, 20, 21];
+/// Muzzle fire frames on the Kenney tilesheet.
+pub const FIRE_FRAMES: [usize; 2] = [295, 296];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AnimationClip {
+    Idle,
+    Walk,
+    Attack,
+    Die,
+    Payout,
+}
+
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub frames: Vec<usize>,
+    pub looping: bool,
+}
+
+/// Plays named clips of atlas indices on a `TextureAtlasSprite`. All clips of
+/// one animation share the same frame rate.
+#[derive(Component, Clone, Debug)]
+pub struct SpriteAnimation {
+    pub clips: HashMap<AnimationClip, Clip>,
+    pub fps: f32,
+    pub current: AnimationClip,
+    pub frame: usize,
+    pub timer: Timer,
+}
+
+impl SpriteAnimation {
+    pub fn new(clips: HashMap<AnimationClip, Clip>, fps: f32, start: AnimationClip) -> Self {
+        SpriteAnimation {
+            clips,
+            fps,
+            current: start,
+            frame: 0,
+            timer: Timer::from_seconds(1.0 / fps, TimerMode::Repeating),
+        }
+    }
+
+    /// Switches to `clip` from its first frame, unless it is already playing.
+    pub fn play(&mut self, clip: AnimationClip) {
+        if self.current != clip && self.clips.contains_key(&clip) {
+            self.current = clip;
+            self.frame = 0;
+            self.timer.reset();
+        }
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.clips.get(&self.current).map_or(0, |clip| clip.frames[self.frame])
+    }
+}
+
+/// Despawns the entity once its non-looping clip has played through.
+#[derive(Component)]
+pub struct DespawnOnFinish;
+
+#[derive(Resource)]
+pub struct Tilesheet(pub Handle<TextureAtlas>);
+
+/// Grid and clip layout written by the `pack_frames` tool next to each sheet.
+pub struct SheetMeta {
+    pub frame_size: Vec2,
+    pub columns: usize,
+    pub rows: usize,
+    pub fps: f32,
+    pub clips: HashMap<String, Vec<usize>>,
+}
+
+pub fn read_sheet_meta(path: &Path) -> Option<SheetMeta> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut meta = SheetMeta {
+        frame_size: Vec2::ZERO,
+        columns: 0,
+        rows: 0,
+        fps: 0.0,
+        clips: HashMap::new(),
+    };
+    for line in contents.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["frame_size", width, height] => meta.frame_size = Vec2::new(width.parse().ok()?, height.parse().ok()?),
+            ["columns", columns] => meta.columns = columns.parse().ok()?,
+            ["rows", rows] => meta.rows = rows.parse().ok()?,
+            ["fps", fps] => meta.fps = fps.parse().ok()?,
+            ["clip", name, first, last] => {
+                let (first, last): (usize, usize) = (first.parse().ok()?, last.parse().ok()?);
+                meta.clips.insert(name.to_string(), (first..=last).collect());
+            }
+            _ => {}
+        }
+    }
+    Some(meta)
+}
+
+/// Where the asset server reads files from, so a file read by hand is found next to the
+/// assets it describes whatever directory the game was started from.
+fn asset_root(asset_server: &AssetServer) -> PathBuf {
+    asset_server.asset_io()
+        .downcast_ref::<FileAssetIo>()
+        .map_or_else(|| PathBuf::from("assets"), |io| io.root_path().clone())
+}
+
+/// Loads a packed sheet from `assets/` and maps its named clips onto animation clips.
+/// Returns `None` when the sheet hasn't been generated, so callers can fall back to a static sprite.
+pub fn load_sheet_animation(
+    asset_server: &AssetServer,
+    atlases: &mut Assets<TextureAtlas>,
+    sheet_path: &str,
+    clips: &[(&str, AnimationClip, bool)],
+    start: AnimationClip,
+) -> Option<(Handle<TextureAtlas>, SpriteAnimation)> {
+    let meta = read_sheet_meta(&asset_root(asset_server).join(sheet_path).with_extension("meta"))?;
+    let atlas = TextureAtlas::from_grid(asset_server.load(sheet_path), meta.frame_size, meta.columns, meta.rows, None, None);
+    let mut animation_clips = HashMap::new();
+    for (name, clip, looping) in clips {
+        let frames = meta.clips.get(*name)?.clone();
+        animation_clips.insert(*clip, Clip { frames, looping: *looping });
+    }
+    Some((atlases.add(atlas), SpriteAnimation::new(animation_clips, meta.fps, start)))
+}
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(PreStartup, load_tilesheet)
+            .add_systems(Update, animate_sprites);
+    }
+}
+
+fn load_tilesheet(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let atlas = TextureAtlas::from_grid(
+        asset_server.load(TILESHEET_PATH),
+        Vec2::splat(TILE_SIZE),
+        TILESHEET_COLUMNS,
+        TILESHEET_ROWS,
+        None,
+        None,
+    );
+    commands.insert_resource(Tilesheet(atlases.add(atlas)));
+}
+
+fn animate_sprites(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(&mut SpriteAnimation, &mut TextureAtlasSprite, Option<&DespawnOnFinish>, Entity)>,
+) {
+    for (mut animation, mut sprite, despawn, entity) in &mut query {
+        animation.timer.tick(time.delta());
+        if animation.timer.just_finished() {
+            let Some(clip) = animation.clips.get(&animation.current) else {
+                continue;
+            };
+            let (length, looping) = (clip.frames.len(), clip.looping);
+            if animation.frame + 1 < length {
+                animation.frame += 1;
+            } else if looping {
+                animation.frame = 0;
+            } else if despawn.is_some() {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            } else {
+                animation.play(AnimationClip::Idle);
+            }
+        }
+        sprite.index = animation.current_index();
+    }
+}
@@ -0,0 +1,116 @@
+//! Packs dumped animation frames into a single sprite sheet for the game.
+//!
+//! The game never decodes video. Dump each clip to PNG frames first, e.g.
+//! `ffmpeg -i assets/art/PigAnimations/Woobling.mp4 frames/Woobling/%04d.png`, then run
+//!
+//! ```text
+//! cargo run --bin pack_frames -- assets/art/PigAnimations/pig_sheet.png \
+//!     Woobling=frames/Woobling DerpyCoin=frames/DerpyCoin --fps 24 --frame-width 400
+//! ```
+//!
+//! This writes the sheet plus a `.meta` file next to it describing the grid and
+//! which frames belong to which clip.
+//!
+//! `scripts/pig_sheet.sh` does both steps for the Money tower's sheet.
+
+use std::{env, fs, path::{Path, PathBuf}, process};
+
+use image::{imageops, RgbaImage};
+
+const MAX_COLUMNS: u32 = 8;
+
+struct ClipFrames {
+    name: String,
+    frames: Vec<PathBuf>,
+}
+
+fn usage() -> ! {
+    eprintln!("usage: pack_frames <output.png> <Clip>=<frames_dir>... [--fps N] [--frame-width W]");
+    process::exit(1);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(output) = args.next() else { usage() };
+    let mut clips = Vec::new();
+    let mut fps = 24.0;
+    let mut frame_width = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fps" => fps = args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage()),
+            "--frame-width" => frame_width = Some(args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage())),
+            _ => {
+                let Some((name, dir)) = arg.split_once('=') else { usage() };
+                clips.push(ClipFrames { name: name.to_string(), frames: list_frames(Path::new(dir)) });
+            }
+        }
+    }
+    if clips.is_empty() {
+        usage();
+    }
+
+    let frames: Vec<RgbaImage> = clips.iter()
+        .flat_map(|clip| clip.frames.iter())
+        .map(|path| {
+            let frame = image::open(path).unwrap_or_else(|err| {
+                eprintln!("could not read {}: {err}", path.display());
+                process::exit(1);
+            }).to_rgba8();
+            match frame_width {
+                Some(width) => {
+                    let height = frame.height() * width / frame.width();
+                    imageops::resize(&frame, width, height, imageops::FilterType::Triangle)
+                }
+                None => frame,
+            }
+        })
+        .collect();
+    let (width, height) = frames[0].dimensions();
+    if frames.iter().any(|frame| frame.dimensions() != (width, height)) {
+        eprintln!("all frames must have the same size");
+        process::exit(1);
+    }
+
+    let columns = MAX_COLUMNS.min(frames.len() as u32);
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let mut sheet = RgbaImage::new(columns * width, rows * height);
+    for (i, frame) in frames.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        imageops::replace(&mut sheet, frame, (column * width) as i64, (row * height) as i64);
+    }
+    sheet.save(&output).unwrap_or_else(|err| {
+        eprintln!("could not write {output}: {err}");
+        process::exit(1);
+    });
+
+    let mut meta = format!("frame_size {width} {height}\ncolumns {columns}\nrows {rows}\nfps {fps}\n");
+    let mut first = 0;
+    for clip in &clips {
+        let last = first + clip.frames.len() - 1;
+        meta += &format!("clip {} {first} {last}\n", clip.name);
+        first = last + 1;
+    }
+    let meta_path = Path::new(&output).with_extension("meta");
+    fs::write(&meta_path, meta).unwrap_or_else(|err| {
+        eprintln!("could not write {}: {err}", meta_path.display());
+        process::exit(1);
+    });
+    println!("packed {} frames into {output}", frames.len());
+}
+
+fn list_frames(dir: &Path) -> Vec<PathBuf> {
+    let mut frames: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|err| {
+            eprintln!("could not read {}: {err}", dir.display());
+            process::exit(1);
+        })
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    frames.sort();
+    if frames.is_empty() {
+        eprintln!("no png frames in {}", dir.display());
+        process::exit(1);
+    }
+    frames
+}
@@ -0,0 +1,379 @@
+//! Runs battles headless to tune tower and enemy numbers.
+//!
+//! A scenario file describes the board, the wave and a build order; every tower in
+//! the build order is bought as soon as it is affordable. For example
+//!
+//! ```json
+//! {
+//!     "lanes": [-2, 2],
+//!     "starting_money": 500,
+//!     "build": [
+//!         { "tower": "Money", "cell": [-7, 0] },
+//!         { "tower": "NormalProjectile", "cell": [-6, 0] }
+//!     ],
+//!     "wave": [["Basic", 10.0], ["Armored", 5.0]]
+//! }
+//! ```
+//!
+//! then run
+//!
+//! ```text
+//! cargo run --release --bin simulate -- scenario.json --seeds 100 --out results
+//! ```
+//!
+//! Missing fields fall back to a day battle on the default board against `WAVE_1`.
+//! This writes `summary.csv` (one row per seed), `money.csv` (money every second)
+//! and `towers.csv` (kills and damage per placed tower) to the output dir. Damage is
+//! health actually removed; kills go to whoever dealt the last hit, or to the mower
+//! that ran the enemy over, so tower kills plus mower kills add up to all kills.
+
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::{Path, PathBuf}, process};
+
+use bevy::{asset::AssetPlugin, prelude::*, time::TimeUpdateStrategy, utils::HashMap};
+use serde::Deserialize;
+
+use pvz_rogue::{
+    GameState,
+    actions::{self, ActionQueue, ActionSet, PlayerAction, ReplayMode},
+    animation,
+    board::*,
+    enemies::{self, Enemies, EnemyDied, EnemyHealth, SpawnTimer},
+    mowers::{self, MowerKill},
+    pickups::{self, CoinMagnet, SkyDrops},
+    rng::RunRng,
+    run::{self, BattleConfig, Run},
+    sim::{self, SimSet},
+    towers::{self, Money, PlacedTower, ProjectileHit, SelectableTowers, Tower},
+};
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct Scenario {
+    lanes: (i32, i32),
+    night: bool,
+    starting_money: u32,
+    /// Picks up every coin as if the player never missed one. Without it coins expire.
+    collect_coins: bool,
+    /// Battles still going after this long are cut off.
+    max_seconds: f32,
+    wave: Vec<(Enemies, f32)>,
+    build: Vec<Placement>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            lanes: (-4, 2),
+            night: false,
+            starting_money: run::STARTING_MONEY,
+            collect_coins: true,
+            max_seconds: 900.0,
+            wave: enemies::WAVE_1.to_vec(),
+            build: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct Placement {
+    tower: Tower,
+    cell: (i32, i32),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Outcome {
+    Won,
+    Lost,
+    TimedOut,
+}
+
+#[derive(Clone, Copy)]
+enum Killer {
+    Tower(TowerId),
+    Mower,
+}
+
+#[derive(Default)]
+struct TowerStats {
+    tower: Option<Tower>,
+    cell: (i32, i32),
+    kills: u32,
+    damage: i64,
+}
+
+/// Everything measured over one battle.
+#[derive(Resource, Default)]
+struct Stats {
+    ticks: u64,
+    outcome: Option<Outcome>,
+    /// Seconds until each lane's mower was first set off.
+    breaches: BTreeMap<i32, f32>,
+    /// Money at the end of each second.
+    money: Vec<u32>,
+    towers: BTreeMap<u32, TowerStats>,
+    kills: u32,
+    mower_kills: u32,
+    /// Who hit each enemy last, so its death can be credited.
+    last_hit: HashMap<Entity, Killer>,
+}
+
+/// Next entry of the build order to buy.
+#[derive(Resource)]
+struct BuildOrder {
+    placements: Vec<Placement>,
+    next: usize,
+}
+
+#[derive(Resource)]
+struct MaxTicks(u64);
+
+fn usage() -> ! {
+    eprintln!("usage: simulate <scenario.json> [--seeds N] [--first-seed S] [--out DIR] [--tick-rate HZ]");
+    process::exit(1);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(scenario_path) = args.next() else { usage() };
+    let mut seeds = 10;
+    let mut first_seed = 0;
+    let mut out = PathBuf::from("simulation");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seeds" => seeds = args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage()),
+            "--first-seed" => first_seed = args.next().and_then(|v| v.parse().ok()).unwrap_or_else(|| usage()),
+            "--out" => out = args.next().map(PathBuf::from).unwrap_or_else(|| usage()),
+            // Read by `sim::tick_rate_from_args`.
+            "--tick-rate" => { args.next(); }
+            _ => usage(),
+        }
+    }
+    let scenario: Scenario = fs::read_to_string(&scenario_path)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| serde_json::from_str(&contents).map_err(|err| err.to_string()))
+        .unwrap_or_else(|err| {
+            eprintln!("could not read {scenario_path}: {err}");
+            process::exit(1);
+        });
+    if scenario.wave.is_empty() {
+        eprintln!("{scenario_path} has an empty wave, there is nothing to simulate");
+        process::exit(1);
+    }
+    let board = generate_board(scenario.lanes);
+    for placement in &scenario.build {
+        if !board.towers.contains_key(&placement.cell) {
+            eprintln!("{:?} at {:?} is off the board and will never be placed", placement.tower, placement.cell);
+        }
+    }
+    let tick_rate = sim::tick_rate_from_args().unwrap_or(sim::DEFAULT_TICK_RATE);
+
+    let mut summary = String::new();
+    let lanes: Vec<i32> = (scenario.lanes.0..=scenario.lanes.1).collect();
+    write!(summary, "seed,outcome,seconds,kills,mower_kills,damage,final_money").unwrap();
+    for lane in &lanes {
+        write!(summary, ",breach_lane_{lane}").unwrap();
+    }
+    summary.push('\n');
+    let mut money = String::from("seed,second,money\n");
+    let mut towers = String::from("seed,tower_id,tower,cell_x,cell_y,kills,damage\n");
+
+    for seed in first_seed..first_seed + seeds {
+        let stats = simulate(&scenario, seed, tick_rate);
+        let seconds = stats.ticks as f64 / tick_rate;
+        let damage: i64 = stats.towers.values().map(|tower| tower.damage).sum();
+        let final_money = stats.money.last().copied().unwrap_or(scenario.starting_money);
+        write!(
+            summary,
+            "{seed},{:?},{seconds:.2},{},{},{damage},{final_money}",
+            stats.outcome.unwrap(), stats.kills, stats.mower_kills,
+        ).unwrap();
+        for lane in &lanes {
+            match stats.breaches.get(lane) {
+                Some(time) => write!(summary, ",{time:.2}").unwrap(),
+                None => summary.push(','),
+            }
+        }
+        summary.push('\n');
+        for (second, value) in stats.money.iter().enumerate() {
+            writeln!(money, "{seed},{},{value}", second + 1).unwrap();
+        }
+        for (id, tower) in &stats.towers {
+            let name = tower.tower.map_or("Unknown".to_string(), |tower| format!("{tower:?}"));
+            writeln!(towers, "{seed},{id},{name},{},{},{},{}", tower.cell.0, tower.cell.1, tower.kills, tower.damage).unwrap();
+        }
+        println!("seed {seed}: {:?} after {seconds:.1}s", stats.outcome.unwrap());
+    }
+
+    write_report(&out, "summary.csv", &summary);
+    write_report(&out, "money.csv", &money);
+    write_report(&out, "towers.csv", &towers);
+}
+
+fn write_report(dir: &Path, file: &str, contents: &str) {
+    let path = dir.join(file);
+    if let Err(err) = fs::create_dir_all(dir).and_then(|_| fs::write(&path, contents)) {
+        eprintln!("could not write {}: {err}", path.display());
+        process::exit(1);
+    }
+}
+
+/// Plays one battle to the end, one fixed tick per update.
+fn simulate(scenario: &Scenario, seed: u64, tick_rate: f64) -> Stats {
+    let mut app = App::new();
+    app
+        .add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .add_asset::<TextureAtlas>()
+        .add_state::<GameState>()
+        .add_plugins((
+            sim::SimPlugin { tick_rate }, actions::ActionPlugin, animation::AnimationPlugin,
+            towers::TowerPlugin, enemies::EnemiesPlugin, pickups::PickupPlugin, mowers::MowerPlugin,
+        ))
+        .insert_resource(ReplayMode::Off)
+        .insert_resource(RunRng::from_seed(seed))
+        .insert_resource(Run { nodes: Vec::new(), current: None, gold: 0, rest_money: 0, upgrades: Vec::new(), relics: Vec::new(), ascension: 0 })
+        .insert_resource(BattleConfig {
+            lanes: scenario.lanes,
+            night: scenario.night,
+            wave: scenario.wave.clone(),
+            starting_money: scenario.starting_money,
+            reward: 0,
+        })
+        .insert_resource(generate_board(scenario.lanes))
+        .insert_resource(Money(scenario.starting_money))
+        .insert_resource(if scenario.night { SkyDrops::night() } else { SkyDrops::day() })
+        .insert_resource(CoinMagnet(scenario.collect_coins))
+        .insert_resource(BuildOrder { placements: scenario.build.clone(), next: 0 })
+        .insert_resource(MaxTicks((scenario.max_seconds as f64 * tick_rate) as u64))
+        .init_resource::<Stats>()
+        .add_systems(Startup, (allow_build_order, enter_battle))
+        .add_systems(FixedUpdate, buy_towers
+            .in_set(SimSet::Gameplay)
+            .before(ActionSet::Dispatch)
+            .run_if(in_state(GameState::Battle)))
+        .add_systems(FixedUpdate, (count_tick, record_towers, record_hits, record_breaches, record_money, check_outcome)
+            .chain()
+            .after(SimSet::Gameplay)
+            .before(SimSet::End)
+            .run_if(in_state(GameState::Battle)));
+
+    let period = app.world.resource::<FixedTime>().period;
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(period));
+    app.finish();
+    app.cleanup();
+    while app.world.resource::<Stats>().outcome.is_none() {
+        app.update();
+    }
+    app.world.remove_resource::<Stats>().unwrap()
+}
+
+fn allow_build_order(mut selectable: ResMut<SelectableTowers>, build: Res<BuildOrder>) {
+    for placement in &build.placements {
+        if !selectable.possible_towers.iter().any(|buyable| buyable.tower_type == placement.tower) {
+            selectable.possible_towers.push(towers::buyable_tower(placement.tower));
+        }
+    }
+}
+
+fn enter_battle(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Battle);
+}
+
+/// Queues the next tower in the build order once there is money for it, like a player would.
+fn buy_towers(
+    mut build: ResMut<BuildOrder>,
+    mut actions: ResMut<ActionQueue>,
+    money: Res<Money>,
+    selectable: Res<SelectableTowers>,
+) {
+    let Some(placement) = build.placements.get(build.next).copied() else {
+        return;
+    };
+    let Some(index) = selectable.possible_towers.iter().position(|buyable| buyable.tower_type == placement.tower) else {
+        return;
+    };
+    if selectable.possible_towers[index].cost > money.0 {
+        return;
+    }
+    actions.push(PlayerAction::SelectTower(index));
+    actions.push(PlayerAction::PlaceTower { cell: placement.cell });
+    build.next += 1;
+}
+
+fn count_tick(mut stats: ResMut<Stats>) {
+    stats.ticks += 1;
+}
+
+fn record_towers(mut stats: ResMut<Stats>, board: Res<Board>, placed: Query<&PlacedTower, Added<PlacedTower>>) {
+    for placed in &placed {
+        let cell = board.towers.iter()
+            .find(|(_, id)| **id == Some(placed.id))
+            .map_or((0, 0), |(cell, _)| *cell);
+        let tower = stats.towers.entry(placed.id.0).or_default();
+        tower.tower = Some(placed.tower);
+        tower.cell = cell;
+    }
+}
+
+fn record_hits(
+    mut stats: ResMut<Stats>,
+    mut hits: EventReader<ProjectileHit>,
+    mut mower_kills: EventReader<MowerKill>,
+    mut deaths: EventReader<EnemyDied>,
+) {
+    for hit in hits.iter() {
+        let Some(source) = hit.source else {
+            continue;
+        };
+        stats.towers.entry(source.0).or_default().damage += hit.dealt as i64;
+        // A shot into an enemy that is already dead didn't kill it.
+        if hit.dealt > 0 {
+            stats.last_hit.insert(hit.target, Killer::Tower(source));
+        }
+    }
+    for kill in mower_kills.iter() {
+        stats.last_hit.insert(kill.target, Killer::Mower);
+    }
+    for death in deaths.iter() {
+        stats.kills += 1;
+        match stats.last_hit.remove(&death.entity) {
+            Some(Killer::Tower(source)) => stats.towers.entry(source.0).or_default().kills += 1,
+            Some(Killer::Mower) => stats.mower_kills += 1,
+            None => {}
+        }
+    }
+}
+
+fn record_breaches(mut stats: ResMut<Stats>, board: Res<Board>, time: Res<FixedTime>) {
+    let now = stats.ticks as f32 * time.period.as_secs_f32();
+    for (lane, state) in &board.mowers {
+        if *state != MowerState::Ready {
+            stats.breaches.entry(*lane).or_insert(now);
+        }
+    }
+}
+
+fn record_money(mut stats: ResMut<Stats>, money: Res<Money>, time: Res<FixedTime>) {
+    // At rates below 1 Hz every tick is more than a second apart.
+    let ticks_per_second = ((1.0 / time.period.as_secs_f64()).round() as u64).max(1);
+    if stats.ticks % ticks_per_second == 0 {
+        stats.money.push(money.0);
+    }
+}
+
+fn check_outcome(
+    mut stats: ResMut<Stats>,
+    max_ticks: Res<MaxTicks>,
+    state: Res<State<GameState>>,
+    next_state: Res<NextState<GameState>>,
+    battle: Res<BattleConfig>,
+    spawn_timers: Query<&SpawnTimer>,
+    enemies: Query<(), With<EnemyHealth>>,
+) {
+    if next_state.0 == Some(GameState::GameOver) || *state.get() == GameState::GameOver {
+        stats.outcome = Some(Outcome::Lost);
+    } else if run::wave_cleared(&battle, &spawn_timers, &enemies) {
+        stats.outcome = Some(Outcome::Won);
+    } else if stats.ticks >= max_ticks.0 {
+        stats.outcome = Some(Outcome::TimedOut);
+    }
+}
@@ -1,26 +1,76 @@
 use std::collections::HashMap;
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 
 pub const GRID_Y_SPACING: f32 = 80.0;
 pub const GRID_X_SPACING: f32 = 80.0;
 
+/// Identifies a placed tower across saves, unlike its `Entity`.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct TowerId(pub u32);
+
+/// The single-use mower guarding the left end of a lane.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MowerState {
+    Ready,
+    Sweeping,
+    Used,
+}
+
 #[derive(Resource)]
 pub struct Board {
-    pub towers: HashMap<(i32,i32), Option<Entity>>,
+    pub towers: HashMap<(i32,i32), Option<TowerId>>,
+    pub mowers: HashMap<i32, MowerState>,
+    pub next_tower_id: u32,
 }
 
 
-pub fn generate_board() -> Board {
+impl Board {
+    pub fn allocate_tower_id(&mut self) -> TowerId {
+        self.next_tower_id += 1;
+        TowerId(self.next_tower_id)
+    }
+
+    /// Empties the cell holding `id`, if any.
+    pub fn remove_tower(&mut self, id: TowerId) {
+        for cell in self.towers.values_mut() {
+            if *cell == Some(id) {
+                *cell = None;
+            }
+        }
+    }
+
+    /// Lowest and highest lane (grid row) on the board.
+    pub fn lane_bounds(&self) -> (i32, i32) {
+        let min = self.towers.keys().map(|pos| pos.1).min().unwrap_or(0);
+        let max = self.towers.keys().map(|pos| pos.1).max().unwrap_or(0);
+        (min, max)
+    }
+
+    /// World x of the board's left border.
+    pub fn left_edge_x(&self) -> f32 {
+        let min_column = self.towers.keys().map(|pos| pos.0).min().unwrap_or(0);
+        min_column as f32 * GRID_X_SPACING
+    }
+}
+
+/// A board of the usual width with lanes `lanes.0..=lanes.1`.
+pub fn generate_board(lanes: (i32, i32)) -> Board {
     let mut board = Board {
         towers: HashMap::new(),
+        mowers: HashMap::new(),
+        next_tower_id: 0,
     };
     for i in -7..7{
-        for j in -4..3 {
+        for j in lanes.0..=lanes.1 {
             board.towers.insert((i as i32, j as i32), None);
         }
     }
+    for j in lanes.0..=lanes.1 {
+        board.mowers.insert(j, MowerState::Ready);
+    }
     return board
 }
 
@@ -32,4 +82,12 @@ pub fn convert_world_to_grid(world: Vec3) -> (i32,i32) {
     let x = (world.x / GRID_X_SPACING as f32).round() as i32;
     let y = (world.y / GRID_Y_SPACING as f32).round() as i32;
     return (x, y);
+}
+
+pub fn convert_lane_to_world_y(lane: i32) -> f32 {
+    return lane as f32 * GRID_Y_SPACING + GRID_Y_SPACING / 2.0;
+}
+
+pub fn convert_world_y_to_lane(y: f32) -> i32 {
+    return ((y - GRID_Y_SPACING / 2.0) / GRID_Y_SPACING).round() as i32;
 }
\ No newline at end of file
@@ -1,16 +1,20 @@
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use rand::Rng;
 use bevy::{prelude::*, utils::HashMap, utils::Duration};
-use crate::{board::*, towers};
+use crate::{GameState, animation::{self, AnimationClip, Clip, DespawnOnFinish, SpriteAnimation, Tilesheet}, board::*, pickups, rng::RunRng, run::{self, BattleConfig}, sim::{GameplaySet, Interpolated}, towers};
 
 #[derive(Component, Clone, Copy)]
 pub struct EnemyHealth {
     pub health: i32,
+    pub max: i32,
 }
 
 #[derive(Component, Clone, Copy)]
 pub struct Walking{
     pub speed: f32,
+    /// -1.0 walks towards the left of the board, 1.0 towards the right.
+    pub direction: f32,
 }
 
 #[derive(Component, Clone, Copy)]
@@ -19,18 +23,170 @@ pub struct Damage{
     pub colliding: bool
 }
 
+#[derive(Component, Clone, Copy)]
+pub struct Bounty {
+    pub money: u32,
+}
+
+#[derive(Component, Clone)]
+pub struct Armor {
+    pub health: i32,
+    /// Tilesheet index the enemy switches to once the armor is gone.
+    pub broken_tile: usize,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct EnemyArchetype(pub Enemies);
+
+/// Spawns `count` enemies of `archetype` in the same lane when this enemy dies.
+#[derive(Component, Clone, Copy)]
+pub struct OnDeathSpawn {
+    pub archetype: Enemies,
+    pub count: usize,
+}
+
+/// Sent when an enemy's health runs out. The entity is despawned after every
+/// death handler has had a chance to read it.
+#[derive(Event)]
+pub struct EnemyDied {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub archetype: Enemies,
+}
+
+const DEATH_SPAWN_SPREAD: f32 = 20.0;
+
+/// The grid row an enemy is walking in.
+#[derive(Component, Clone, Copy)]
+pub struct Lane(pub i32);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LaneSwitchTrigger {
+    OnHit,
+    OnBlocked,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct LaneSwitcher {
+    pub trigger: LaneSwitchTrigger,
+    pub speed: f32,
+    pub switches_left: u32,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Vaulter {
+    pub jump_duration: f32,
+    pub speed_after: f32,
+    pub vaulted: bool,
+}
+
+/// What an enemy is currently doing. Only `Walking` enemies move along their lane.
+#[derive(Component, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EnemyState {
+    Walking,
+    SwitchingLane { target: i32 },
+    Vaulting { from_x: f32, to_x: f32, elapsed: f32 },
+    Summoning { elapsed: f32 },
+    Shooting,
+    Burrowing,
+}
+
+/// Periodically stops to spawn `minion`s into its own and the adjacent lanes.
+#[derive(Component, Clone)]
+pub struct Summoner {
+    pub minion: Enemies,
+    pub interval: Timer,
+    pub channel_time: f32,
+}
+
+/// Stops `range` away from the first tower in its lane and shoots at it.
+#[derive(Component, Clone)]
+pub struct Ranged {
+    pub range: f32,
+    pub projectile_speed: f32,
+    pub damage: i32,
+    pub shoot_timer: Timer,
+}
+
+/// Enemy-side counterpart of `towers::Projectile`, flying leftward into towers.
+#[derive(Component)]
+pub struct EnemyProjectile {
+    pub speed: f32,
+    pub damage: i32,
+}
+
+/// Tunnels under towers while `Burrowing`, then surfaces just inside the board's left edge
+/// and walks back right.
+#[derive(Component, Clone, Copy)]
+pub struct Digger;
+
+/// Flying enemies pass over towers and can only be hit by anti-air projectiles.
+#[derive(Component, Clone, Copy)]
+pub struct Flying;
+
+#[derive(Clone, Copy)]
+pub enum BossAction {
+    SetSpeed(f32),
+    Summon { archetype: Enemies, count: usize },
+    Smash { damage: i32 },
+}
+
+/// Fires `action` once the boss drops to `threshold` of its max health.
+#[derive(Clone, Copy)]
+pub struct BossPhase {
+    pub threshold: f32,
+    pub action: BossAction,
+}
+
+#[derive(Component, Clone)]
+pub struct Boss {
+    pub phases: Vec<BossPhase>,
+    pub next_phase: usize,
+}
+
+/// Enemies with this marker can't be shot at or hit by projectiles.
+#[derive(Component)]
+pub struct Untargetable;
+
+const VAULT_HEIGHT: f32 = 40.0;
+
 #[derive(Bundle, Clone)]
 struct BasicEnemy {
     health: EnemyHealth,
     walking: Walking,
     damage: Damage,
+    bounty: Bounty,
 }
 
-#[derive(Eq, PartialEq, Hash, Debug)]
-enum Enemies{
+#[derive(Clone)]
+enum EnemyExtra {
+    Armor(Armor),
+    LaneSwitcher(LaneSwitcher),
+    Vaulter(Vaulter),
+    Flying,
+    Boss(Boss),
+    Summoner(Summoner),
+    Ranged(Ranged),
+    OnDeathSpawn(OnDeathSpawn),
+    Digger,
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Enemies{
     Basic,
     BasicHighHealth,
     FastBasic,
+    Armored,
+    HeavyArmored,
+    Dodger,
+    Sidestepper,
+    PoleVaulter,
+    Flyer,
+    Boss,
+    Summoner,
+    Gunner,
+    Splitter,
+    Digger,
 }
 
 #[derive(Component)]
@@ -39,24 +195,166 @@ pub struct SpawnTimer {
     pub index: usize,
 }
 
+/// Kenney units are single tiles, so walking holds the tile and attacking flickers muzzle fire.
+fn enemy_animation(tile: usize, fps: f32) -> SpriteAnimation {
+    let mut clips = HashMap::new();
+    clips.insert(AnimationClip::Idle, Clip { frames: vec![tile], looping: true });
+    clips.insert(AnimationClip::Walk, Clip { frames: vec![tile], looping: true });
+    clips.insert(AnimationClip::Attack, Clip {
+        frames: vec![tile, animation::FIRE_FRAMES[0], tile, animation::FIRE_FRAMES[1]],
+        looping: true,
+    });
+    clips.insert(AnimationClip::Die, Clip { frames: animation::EXPLOSION_FRAMES.to_vec(), looping: false });
+    SpriteAnimation::new(clips, fps, AnimationClip::Walk)
+}
+
 lazy_static! {
-    static ref ENEMY_IMPLEMENTATIONS: HashMap<Enemies, (BasicEnemy, String)> = {
+    static ref ENEMY_IMPLEMENTATIONS: HashMap<Enemies, (BasicEnemy, SpriteAnimation, Vec<EnemyExtra>)> = {
         let mut map = HashMap::new();
         map.insert(Enemies::Basic, (BasicEnemy {
-            health: EnemyHealth { health: 100 },
-            walking: Walking { speed: 15.0 },
+            health: EnemyHealth { health: 100, max: 100 },
+            walking: Walking { speed: 15.0, direction: -1.0 },
             damage: Damage { damage: 10, colliding: false },
-        }, "kenney/PNG/DefaultSize/towerDefense_tile247.png".to_string()));
+            bounty: Bounty { money: 25 },
+        }, enemy_animation(247, 4.0), vec![]));
         map.insert(Enemies::BasicHighHealth, (BasicEnemy {
-            health: EnemyHealth { health: 300 },
-            walking: Walking { speed: 15.0 },
+            health: EnemyHealth { health: 300, max: 300 },
+            walking: Walking { speed: 15.0, direction: -1.0 },
             damage: Damage { damage: 10, colliding: false },
-        }, "kenney/PNG/DefaultSize/towerDefense_tile246.png".to_string()));
+            bounty: Bounty { money: 50 },
+        }, enemy_animation(246, 3.0), vec![]));
         map.insert(Enemies::FastBasic, (BasicEnemy {
-            health: EnemyHealth { health: 100 },
-            walking: Walking { speed: 35.0 },
+            health: EnemyHealth { health: 100, max: 100 },
+            walking: Walking { speed: 35.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 35 },
+        }, enemy_animation(248, 8.0), vec![]));
+        map.insert(Enemies::Armored, (BasicEnemy {
+            health: EnemyHealth { health: 100, max: 100 },
+            walking: Walking { speed: 15.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 50 },
+        }, enemy_animation(268, 3.0), vec![
+            EnemyExtra::Armor(Armor {
+                health: 100,
+                broken_tile: 245,
+            }),
+        ]));
+        map.insert(Enemies::HeavyArmored, (BasicEnemy {
+            health: EnemyHealth { health: 100, max: 100 },
+            walking: Walking { speed: 12.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 75 },
+        }, enemy_animation(269, 2.0), vec![
+            EnemyExtra::Armor(Armor {
+                health: 250,
+                broken_tile: 247,
+            }),
+        ]));
+        map.insert(Enemies::Dodger, (BasicEnemy {
+            health: EnemyHealth { health: 120, max: 120 },
+            walking: Walking { speed: 20.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 40 },
+        }, enemy_animation(245, 5.0), vec![
+            EnemyExtra::LaneSwitcher(LaneSwitcher {
+                trigger: LaneSwitchTrigger::OnHit,
+                speed: 60.0,
+                switches_left: 1,
+            }),
+        ]));
+        map.insert(Enemies::Sidestepper, (BasicEnemy {
+            health: EnemyHealth { health: 150, max: 150 },
+            walking: Walking { speed: 15.0, direction: -1.0 },
             damage: Damage { damage: 10, colliding: false },
-        }, "kenney/PNG/DefaultSize/towerDefense_tile248.png".to_string()));
+            bounty: Bounty { money: 40 },
+        }, enemy_animation(248, 4.0), vec![
+            EnemyExtra::LaneSwitcher(LaneSwitcher {
+                trigger: LaneSwitchTrigger::OnBlocked,
+                speed: 40.0,
+                switches_left: 2,
+            }),
+        ]));
+        map.insert(Enemies::PoleVaulter, (BasicEnemy {
+            health: EnemyHealth { health: 150, max: 150 },
+            walking: Walking { speed: 30.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 40 },
+        }, enemy_animation(246, 6.0), vec![
+            EnemyExtra::Vaulter(Vaulter {
+                jump_duration: 1.0,
+                speed_after: 12.0,
+                vaulted: false,
+            }),
+        ]));
+        map.insert(Enemies::Flyer, (BasicEnemy {
+            health: EnemyHealth { health: 80, max: 80 },
+            walking: Walking { speed: 25.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 45 },
+        }, enemy_animation(270, 8.0), vec![
+            EnemyExtra::Flying,
+        ]));
+        map.insert(Enemies::Boss, (BasicEnemy {
+            health: EnemyHealth { health: 3000, max: 3000 },
+            walking: Walking { speed: 8.0, direction: -1.0 },
+            damage: Damage { damage: 30, colliding: false },
+            bounty: Bounty { money: 500 },
+        }, enemy_animation(250, 2.0), vec![
+            EnemyExtra::Boss(Boss {
+                phases: vec![
+                    BossPhase { threshold: 0.66, action: BossAction::Summon { archetype: Enemies::Basic, count: 3 } },
+                    BossPhase { threshold: 0.66, action: BossAction::Smash { damage: 200 } },
+                    BossPhase { threshold: 0.33, action: BossAction::SetSpeed(20.0) },
+                    BossPhase { threshold: 0.33, action: BossAction::Summon { archetype: Enemies::FastBasic, count: 2 } },
+                ],
+                next_phase: 0,
+            }),
+        ]));
+        map.insert(Enemies::Summoner, (BasicEnemy {
+            health: EnemyHealth { health: 250, max: 250 },
+            walking: Walking { speed: 12.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 80 },
+        }, enemy_animation(292, 3.0), vec![
+            EnemyExtra::Summoner(Summoner {
+                minion: Enemies::Basic,
+                interval: Timer::from_seconds(8.0, TimerMode::Repeating),
+                channel_time: 1.5,
+            }),
+        ]));
+        map.insert(Enemies::Gunner, (BasicEnemy {
+            health: EnemyHealth { health: 120, max: 120 },
+            walking: Walking { speed: 15.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 50 },
+        }, enemy_animation(291, 4.0), vec![
+            EnemyExtra::Ranged(Ranged {
+                range: GRID_X_SPACING * 3.0,
+                projectile_speed: 150.0,
+                damage: 10,
+                shoot_timer: Timer::from_seconds(3.0, TimerMode::Repeating),
+            }),
+        ]));
+        map.insert(Enemies::Splitter, (BasicEnemy {
+            health: EnemyHealth { health: 200, max: 200 },
+            walking: Walking { speed: 12.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 30 },
+        }, enemy_animation(205, 3.0), vec![
+            EnemyExtra::OnDeathSpawn(OnDeathSpawn {
+                archetype: Enemies::FastBasic,
+                count: 3,
+            }),
+        ]));
+        map.insert(Enemies::Digger, (BasicEnemy {
+            health: EnemyHealth { health: 150, max: 150 },
+            walking: Walking { speed: 20.0, direction: -1.0 },
+            damage: Damage { damage: 10, colliding: false },
+            bounty: Bounty { money: 60 },
+        }, enemy_animation(247, 5.0), vec![
+            EnemyExtra::Digger,
+        ]));
         map
     };
 }
@@ -75,7 +373,7 @@ const TEST_WAVE: [(Enemies, f32); 10] = [
 ];
 
 
-const WAVE_1: [(Enemies, f32); 22] = [
+pub const WAVE_1: [(Enemies, f32); 33] = [
     (Enemies::Basic, 45.0),
     (Enemies::Basic, 25.0),
     (Enemies::Basic, 15.0),
@@ -84,11 +382,16 @@ const WAVE_1: [(Enemies, f32); 22] = [
     (Enemies::BasicHighHealth, 20.0),
     (Enemies::FastBasic, 15.0),
     (Enemies::BasicHighHealth, 15.0),
+    (Enemies::Armored, 10.0),
+    (Enemies::Sidestepper, 5.0),
     (Enemies::Basic, 5.0),
     (Enemies::Basic, 0.3),
     (Enemies::Basic, 0.3),
     (Enemies::Basic, 0.3),
     (Enemies::BasicHighHealth, 20.0),
+    (Enemies::Dodger, 3.0),
+    (Enemies::PoleVaulter, 3.0),
+    (Enemies::Flyer, 3.0),
     (Enemies::FastBasic, 3.0),
     (Enemies::Basic, 0.0),
     (Enemies::Basic, 0.0),
@@ -98,17 +401,15 @@ const WAVE_1: [(Enemies, f32); 22] = [
     (Enemies::BasicHighHealth, 0.0),
     (Enemies::FastBasic, 0.0),
     (Enemies::FastBasic, 0.0),
+    (Enemies::HeavyArmored, 0.0),
+    (Enemies::Summoner, 5.0),
+    (Enemies::Gunner, 3.0),
+    (Enemies::Splitter, 3.0),
+    (Enemies::Digger, 3.0),
+    (Enemies::Boss, 20.0),
 ];
 
-const SPAWN_POSITIONS: [f32; 7] = [
-    GRID_Y_SPACING * -1.0,
-    GRID_Y_SPACING * -2.0,
-    GRID_Y_SPACING * -3.0,
-    GRID_Y_SPACING * -4.0,
-    0.0,
-    GRID_Y_SPACING,
-    GRID_Y_SPACING * 2.0,
-];
+const ENEMY_SPAWN_X: f32 = 500.0;
 
 
 pub struct EnemiesPlugin;
@@ -116,88 +417,545 @@ pub struct EnemiesPlugin;
 impl Plugin for EnemiesPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(Startup, setup)
-            .add_systems(Update, (walk_enemies, check_enemy_health, update_spawn_timer, enemies_damage_towers));
+            .add_event::<EnemyDied>()
+            .add_systems(OnEnter(GameState::Battle), start_wave.after(run::start_battle))
+            .add_systems(FixedUpdate, (
+                (update_spawn_timer, trigger_lane_switch, boss_phases, summon_minions, ranged_attack)
+                    .chain()
+                    .in_set(GameplaySet::Act),
+                (walk_enemies, switch_lanes, vault_enemies, surface_diggers, despawn_escaped_diggers, move_enemy_projectiles)
+                    .chain()
+                    .in_set(GameplaySet::Move),
+                (enemies_damage_towers, enemy_projectiles_damage_towers)
+                    .chain()
+                    .in_set(GameplaySet::Combat),
+                break_armor.in_set(GameplaySet::Resolve))
+                .run_if(in_state(GameState::Battle)))
+            .add_systems(FixedUpdate, (
+                check_enemy_health, drop_bounty, spawn_on_death, spawn_death_effect, despawn_dead_enemies).chain()
+                .in_set(GameplaySet::Resolve))
+            .add_systems(Update, update_enemy_clips.run_if(in_state(GameState::Battle)));
     }
 }
 
-fn setup(mut commands: Commands) {
+pub fn start_wave(mut commands: Commands, battle: Res<BattleConfig>) {
     commands.spawn(SpawnTimer {
-        timer: Timer::from_seconds(WAVE_1[0].1, TimerMode::Repeating),
+        timer: Timer::from_seconds(battle.wave[0].1, TimerMode::Repeating),
         index: 0,
     });
 }
 
 fn update_spawn_timer(
-    time: Res<Time>, mut spawn_timer: Query<&mut SpawnTimer>,
-    mut commands: Commands, asset_server: Res<AssetServer>
+    time: Res<FixedTime>, mut spawn_timer: Query<&mut SpawnTimer>,
+    mut commands: Commands, tilesheet: Res<Tilesheet>, mut run_rng: ResMut<RunRng>,
+    battle: Res<BattleConfig>, board: Res<Board>
 ) {
+    let (min_lane, max_lane) = board.lane_bounds();
     for mut timer in spawn_timer.iter_mut() {
-        timer.timer.tick(time.delta());                                                 
+        timer.timer.tick(time.period);                                                 
         if timer.timer.finished() {
-            if timer.index >= WAVE_1.len() {
+            if timer.index >= battle.wave.len() {
                 return;
             } else {
-                let enemy_timing = &WAVE_1[timer.index];
+                let enemy_timing = &battle.wave[timer.index];
                 timer.timer.set_duration(Duration::from_secs_f32(enemy_timing.1));
                 println!("Timer: {:?}", timer.timer.duration());
-                let random_index = rand::thread_rng().gen_range(0..=6);
-                let enemy = &ENEMY_IMPLEMENTATIONS[&enemy_timing.0];
-                let texture: Handle<Image> = asset_server.load(&enemy.1);
-                spawn_enemy(&mut commands, texture, SPAWN_POSITIONS[random_index], enemy.0.clone());
+                let lane = run_rng.rng.gen_range(min_lane..=max_lane);
+                let position = Vec3::new(ENEMY_SPAWN_X, convert_lane_to_world_y(lane), 0.0);
+                spawn_enemy(&mut commands, &tilesheet, enemy_timing.0, position);
             }
             timer.index += 1;
         }
     }
 }
 
-fn spawn_enemy(commands: &mut Commands, texture: Handle<Image>, y: f32, enemy: BasicEnemy) {
-    commands.spawn((SpriteBundle {
-        texture,
+/// Spawns `archetype` at `position`, which should sit on a lane's y.
+pub fn spawn_enemy(commands: &mut Commands, tilesheet: &Res<Tilesheet>, archetype: Enemies, position: Vec3) -> Entity {
+    let enemy = &ENEMY_IMPLEMENTATIONS[&archetype];
+    let mut ent = commands.spawn((SpriteSheetBundle {
+        sprite: TextureAtlasSprite::new(enemy.1.current_index()),
+        texture_atlas: tilesheet.0.clone(),
         transform: Transform {
-            translation: Vec3::new(500.0, y + GRID_Y_SPACING / 2.0, 0.0),
+            translation: position,
             rotation: Quat::from_rotation_y(std::f32::consts::PI),
             scale: Vec3::new(1.0, -1.0, 1.0),
         },
         ..default()
-    }, enemy)
+    }, enemy.0.clone(), enemy.1.clone(), EnemyArchetype(archetype), Lane(convert_world_y_to_lane(position.y)), EnemyState::Walking,
+        Interpolated::at(position))
     );
+    for extra in &enemy.2 {
+        match extra {
+            EnemyExtra::Armor(armor) => ent.insert(armor.clone()),
+            EnemyExtra::LaneSwitcher(switcher) => ent.insert(*switcher),
+            EnemyExtra::Vaulter(vaulter) => ent.insert(*vaulter),
+            EnemyExtra::Flying => ent.insert(Flying),
+            EnemyExtra::Boss(boss) => ent.insert(boss.clone()),
+            EnemyExtra::Summoner(summoner) => ent.insert(summoner.clone()),
+            EnemyExtra::Ranged(ranged) => ent.insert(ranged.clone()),
+            EnemyExtra::OnDeathSpawn(on_death) => ent.insert(*on_death),
+            EnemyExtra::Digger => ent.insert((Digger, EnemyState::Burrowing, Untargetable)),
+        };
+    }
+    ent.id()
+}
+
+pub fn spawn_enemy_projectile(
+    commands: &mut Commands, asset_server: &Res<AssetServer>, position: Vec3, projectile: EnemyProjectile
+) {
+    commands.spawn((SpriteBundle {
+        texture: asset_server.load("kenney/PNG/DefaultSize/towerDefense_tile272.png"),
+        transform: Transform::from_translation(position),
+        ..default()
+    }, projectile, Interpolated::at(position)));
 }
 
 fn walk_enemies(
-    mut enemies: Query<(&mut Transform, &Walking, &Damage)>,
-    time: Res<Time>
+    mut enemies: Query<(&mut Transform, &Walking, &Damage, &EnemyState)>,
+    time: Res<FixedTime>
 ) {
-    for (mut transform, enemy, enemy_damage) in enemies.iter_mut() {
-        if !enemy_damage.colliding {
-            transform.translation.x -= enemy.speed * time.delta_seconds();
+    for (mut transform, enemy, enemy_damage, state) in enemies.iter_mut() {
+        let moving = match *state {
+            EnemyState::Walking => !enemy_damage.colliding,
+            EnemyState::Burrowing => true,
+            _ => false,
+        };
+        if moving {
+            transform.translation.x += enemy.speed * enemy.direction * time.period.as_secs_f32();
         }
     }
 }
 
-fn check_enemy_health(
+fn trigger_lane_switch(
+    board: Res<Board>,
+    mut run_rng: ResMut<RunRng>,
+    mut switchers: Query<(&mut LaneSwitcher, &mut EnemyState, &Lane, &Damage, Ref<EnemyHealth>)>,
+) {
+    let (min_lane, max_lane) = board.lane_bounds();
+    for (mut switcher, mut state, lane, damage, health) in switchers.iter_mut() {
+        if switcher.switches_left == 0 || *state != EnemyState::Walking {
+            continue;
+        }
+        let triggered = match switcher.trigger {
+            LaneSwitchTrigger::OnHit => health.is_changed() && !health.is_added(),
+            LaneSwitchTrigger::OnBlocked => damage.colliding,
+        };
+        if !triggered {
+            continue;
+        }
+        let step = if run_rng.rng.gen_bool(0.5) { 1 } else { -1 };
+        let target = if (min_lane..=max_lane).contains(&(lane.0 + step)) { lane.0 + step } else { lane.0 - step };
+        if !(min_lane..=max_lane).contains(&target) {
+            continue;
+        }
+        switcher.switches_left -= 1;
+        *state = EnemyState::SwitchingLane { target };
+    }
+}
+
+/// Slides enemies vertically into their new lane. The lane only changes once the
+/// enemy sits exactly on the lane's y, so towers and collisions never see it half way.
+fn switch_lanes(
+    mut enemies: Query<(&mut Transform, &mut Lane, &mut EnemyState, &LaneSwitcher)>,
+    time: Res<FixedTime>,
+) {
+    for (mut transform, mut lane, mut state, switcher) in enemies.iter_mut() {
+        let EnemyState::SwitchingLane { target } = *state else {
+            continue;
+        };
+        let target_y = convert_lane_to_world_y(target);
+        let step = switcher.speed * time.period.as_secs_f32();
+        if (target_y - transform.translation.y).abs() <= step {
+            transform.translation.y = target_y;
+            lane.0 = target;
+            *state = EnemyState::Walking;
+        } else {
+            transform.translation.y += step * (target_y - transform.translation.y).signum();
+        }
+    }
+}
+
+/// Moves vaulting enemies along a jump arc and lands them back in their lane as slower walkers.
+fn vault_enemies(
     mut commands: Commands,
-    query: Query<(&EnemyHealth, Entity), Changed<EnemyHealth>>,
+    mut enemies: Query<(&mut Transform, &mut EnemyState, &mut Walking, &Vaulter, &Lane, Entity)>,
+    time: Res<FixedTime>,
 ) {
-    for (health, entity) in query.iter() {
-        if health.health <= 0 {
+    for (mut transform, mut state, mut walking, vaulter, lane, entity) in enemies.iter_mut() {
+        let EnemyState::Vaulting { from_x, to_x, elapsed } = *state else {
+            continue;
+        };
+        let elapsed = elapsed + time.period.as_secs_f32();
+        let progress = (elapsed / vaulter.jump_duration).min(1.0);
+        let lane_y = convert_lane_to_world_y(lane.0);
+        transform.translation.x = from_x + (to_x - from_x) * progress;
+        if progress >= 1.0 {
+            transform.translation.y = lane_y;
+            walking.speed = vaulter.speed_after;
+            *state = EnemyState::Walking;
+            commands.entity(entity).remove::<Untargetable>();
+        } else {
+            transform.translation.y = lane_y + (progress * std::f32::consts::PI).sin() * VAULT_HEIGHT;
+            *state = EnemyState::Vaulting { from_x, to_x, elapsed };
+        }
+    }
+}
+
+fn boss_phases(
+    mut commands: Commands,
+    tilesheet: Res<Tilesheet>,
+    board: Res<Board>,
+    mut run_rng: ResMut<RunRng>,
+    mut bosses: Query<(&EnemyHealth, &mut Boss, &mut Walking, &Lane, &Transform), Changed<EnemyHealth>>,
+    mut towers: Query<(&Transform, &mut towers::Health), Without<Boss>>,
+) {
+    let (min_lane, max_lane) = board.lane_bounds();
+    for (health, mut boss, mut walking, lane, transform) in bosses.iter_mut() {
+        while boss.next_phase < boss.phases.len()
+            && health.health as f32 / health.max as f32 <= boss.phases[boss.next_phase].threshold
+        {
+            match boss.phases[boss.next_phase].action {
+                BossAction::SetSpeed(speed) => walking.speed = speed,
+                BossAction::Summon { archetype, count } => {
+                    for _ in 0..count {
+                        let mut minion_lane = run_rng.rng.gen_range(min_lane..=max_lane);
+                        if minion_lane == lane.0 {
+                            minion_lane = if minion_lane == max_lane { min_lane } else { minion_lane + 1 };
+                        }
+                        let position = Vec3::new(ENEMY_SPAWN_X, convert_lane_to_world_y(minion_lane), 0.0);
+                        spawn_enemy(&mut commands, &tilesheet, archetype, position);
+                    }
+                }
+                BossAction::Smash { damage } => {
+                    let target = towers.iter_mut()
+                        .filter(|(tower_transform, _)| {
+                            tower_transform.translation.y == transform.translation.y && tower_transform.translation.x < transform.translation.x
+                        })
+                        .max_by(|(a, _), (b, _)| a.translation.x.total_cmp(&b.translation.x));
+                    if let Some((_, mut tower_health)) = target {
+                        tower_health.health -= damage;
+                    }
+                }
+            }
+            boss.next_phase += 1;
+        }
+    }
+}
+
+fn summon_minions(
+    mut commands: Commands,
+    tilesheet: Res<Tilesheet>,
+    board: Res<Board>,
+    mut summoners: Query<(&mut Summoner, &mut EnemyState, &Lane, &Transform)>,
+    time: Res<FixedTime>,
+) {
+    let (min_lane, max_lane) = board.lane_bounds();
+    for (mut summoner, mut state, lane, transform) in summoners.iter_mut() {
+        match *state {
+            EnemyState::Walking => {
+                summoner.interval.tick(time.period);
+                if !summoner.interval.just_finished() {
+                    continue;
+                }
+                for minion_lane in lane.0 - 1..=lane.0 + 1 {
+                    if !(min_lane..=max_lane).contains(&minion_lane) {
+                        continue;
+                    }
+                    let position = Vec3::new(transform.translation.x, convert_lane_to_world_y(minion_lane), 0.0);
+                    spawn_enemy(&mut commands, &tilesheet, summoner.minion, position);
+                }
+                *state = EnemyState::Summoning { elapsed: 0.0 };
+            }
+            EnemyState::Summoning { elapsed } => {
+                let elapsed = elapsed + time.period.as_secs_f32();
+                *state = if elapsed >= summoner.channel_time {
+                    EnemyState::Walking
+                } else {
+                    EnemyState::Summoning { elapsed }
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+fn ranged_attack(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut shooters: Query<(&mut Ranged, &mut EnemyState, &Transform)>,
+    towers: Query<&Transform, With<towers::Health>>,
+    time: Res<FixedTime>,
+) {
+    for (mut ranged, mut state, transform) in shooters.iter_mut() {
+        if *state != EnemyState::Walking && *state != EnemyState::Shooting {
+            continue;
+        }
+        let distance = towers.iter()
+            .filter(|tower_transform| {
+                tower_transform.translation.y == transform.translation.y && tower_transform.translation.x < transform.translation.x
+            })
+            .map(|tower_transform| transform.translation.x - tower_transform.translation.x)
+            .min_by(|a, b| a.total_cmp(b));
+        if distance.map_or(true, |distance| distance > ranged.range) {
+            *state = EnemyState::Walking;
+            continue;
+        }
+        *state = EnemyState::Shooting;
+        ranged.shoot_timer.tick(time.period);
+        if ranged.shoot_timer.just_finished() {
+            spawn_enemy_projectile(&mut commands, &asset_server, transform.translation, EnemyProjectile {
+                speed: ranged.projectile_speed,
+                damage: ranged.damage,
+            });
+        }
+    }
+}
+
+fn move_enemy_projectiles(
+    mut commands: Commands,
+    mut projectiles: Query<(&mut Transform, &EnemyProjectile, Entity)>,
+    time: Res<FixedTime>,
+) {
+    for (mut transform, projectile, entity) in projectiles.iter_mut() {
+        transform.translation.x -= projectile.speed * time.period.as_secs_f32();
+        if transform.translation.x < -650.0 {
             commands.entity(entity).despawn();
         }
     }
 }
 
+fn enemy_projectiles_damage_towers(
+    mut commands: Commands,
+    mut projectile_hit: EventWriter<towers::ProjectileHit>,
+    projectiles: Query<(&Transform, &EnemyProjectile, Entity)>,
+    mut towers: Query<(&Transform, &mut towers::Health, Entity)>,
+) {
+    for (transform, projectile, entity) in projectiles.iter() {
+        for (tower_transform, mut tower, tower_ent) in &mut towers {
+            if transform.translation.y == tower_transform.translation.y && (transform.translation.x - tower_transform.translation.x).abs() < 10.0 {
+                let dealt = projectile.damage.min(tower.health.max(0));
+                tower.health -= projectile.damage;
+                projectile_hit.send(towers::ProjectileHit {
+                    target: tower_ent,
+                    damage: projectile.damage,
+                    dealt,
+                    position: tower_transform.translation,
+                    source: None,
+                });
+                commands.entity(entity).despawn();
+                break;
+            }
+        }
+    }
+}
+
+fn surface_diggers(
+    mut commands: Commands,
+    board: Res<Board>,
+    mut diggers: Query<(&mut Transform, &mut Walking, &mut EnemyState, &mut TextureAtlasSprite, Entity), With<Digger>>,
+) {
+    // Half a cell in, so surfacing never crosses the mowers' breach line.
+    let surface_x = board.left_edge_x() + GRID_X_SPACING / 2.0;
+    for (mut transform, mut walking, mut state, mut sprite, entity) in diggers.iter_mut() {
+        if *state == EnemyState::Burrowing {
+            sprite.color.set_a(0.4);
+            if transform.translation.x <= surface_x {
+                *state = EnemyState::Walking;
+                walking.direction = 1.0;
+                transform.rotation = Quat::IDENTITY;
+                transform.scale = Vec3::ONE;
+                sprite.color.set_a(1.0);
+                commands.entity(entity).remove::<Untargetable>();
+            }
+        }
+    }
+}
+
+/// Surfaced diggers that make it back out past the spawn point are gone for good.
+fn despawn_escaped_diggers(mut commands: Commands, diggers: Query<(&Transform, &Walking, Entity), With<Digger>>) {
+    for (transform, walking, entity) in diggers.iter() {
+        if walking.direction > 0.0 && transform.translation.x >= ENEMY_SPAWN_X {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn check_enemy_health(
+    mut enemy_died: EventWriter<EnemyDied>,
+    query: Query<(&EnemyHealth, &EnemyArchetype, &Transform, Entity), Changed<EnemyHealth>>,
+) {
+    for (health, archetype, transform, entity) in query.iter() {
+        if health.health <= 0 {
+            enemy_died.send(EnemyDied {
+                entity,
+                position: transform.translation,
+                archetype: archetype.0,
+            });
+        }
+    }
+}
+
+fn drop_bounty(
+    mut commands: Commands,
+    mut enemy_died: EventReader<EnemyDied>,
+    asset_server: Res<AssetServer>,
+    bounties: Query<&Bounty>,
+) {
+    for died in enemy_died.iter() {
+        let Ok(bounty) = bounties.get(died.entity) else {
+            continue;
+        };
+        pickups::spawn_coin(&mut commands, &asset_server, bounty.money, died.position);
+    }
+}
+
+fn spawn_on_death(
+    mut commands: Commands,
+    mut enemy_died: EventReader<EnemyDied>,
+    tilesheet: Res<Tilesheet>,
+    spawners: Query<(&OnDeathSpawn, &Lane)>,
+) {
+    for died in enemy_died.iter() {
+        let Ok((on_death, lane)) = spawners.get(died.entity) else {
+            continue;
+        };
+        let offset = (on_death.count as f32 - 1.0) / 2.0;
+        for i in 0..on_death.count {
+            let x = died.position.x + (i as f32 - offset) * DEATH_SPAWN_SPREAD;
+            let position = Vec3::new(x, convert_lane_to_world_y(lane.0), 0.0);
+            spawn_enemy(&mut commands, &tilesheet, on_death.archetype, position);
+        }
+    }
+}
+
+/// Leaves an explosion playing the archetype's die clip where the enemy fell.
+fn spawn_death_effect(
+    mut commands: Commands,
+    mut enemy_died: EventReader<EnemyDied>,
+    tilesheet: Res<Tilesheet>,
+) {
+    for died in enemy_died.iter() {
+        let mut animation = ENEMY_IMPLEMENTATIONS[&died.archetype].1.clone();
+        animation.play(AnimationClip::Die);
+        commands.spawn((SpriteSheetBundle {
+            sprite: TextureAtlasSprite::new(animation.current_index()),
+            texture_atlas: tilesheet.0.clone(),
+            transform: Transform::from_translation(died.position),
+            ..default()
+        }, animation, DespawnOnFinish));
+    }
+}
+
+pub fn despawn_dead_enemies(
+    mut commands: Commands,
+    mut enemy_died: EventReader<EnemyDied>,
+) {
+    for died in enemy_died.iter() {
+        commands.entity(died.entity).despawn_recursive();
+    }
+}
+
+/// Armor soaks up damage before health unless the damage type bypasses it.
+/// Returns how much health the enemy lost.
+pub fn apply_damage(health: &mut EnemyHealth, armor: Option<Mut<Armor>>, damage: i32, damage_type: towers::DamageType) -> i32 {
+    let mut remaining = damage;
+    if let Some(mut armor) = armor {
+        if armor.health > 0 && !damage_type.bypasses_armor() {
+            let absorbed = remaining.min(armor.health);
+            armor.health -= absorbed;
+            remaining -= absorbed;
+        }
+    }
+    let dealt = remaining.min(health.health.max(0));
+    health.health -= remaining;
+    dealt
+}
+
+fn break_armor(
+    mut commands: Commands,
+    mut query: Query<(&Armor, &mut SpriteAnimation, Entity), Changed<Armor>>,
+) {
+    for (armor, mut animation, entity) in query.iter_mut() {
+        if armor.health <= 0 {
+            *animation = enemy_animation(armor.broken_tile, animation.fps);
+            commands.entity(entity).remove::<Armor>();
+        }
+    }
+}
+
+fn update_enemy_clips(
+    mut enemies: Query<(&Damage, &EnemyState, &mut SpriteAnimation)>,
+) {
+    for (damage, state, mut animation) in enemies.iter_mut() {
+        if damage.colliding || *state == EnemyState::Shooting {
+            animation.play(AnimationClip::Attack);
+        } else {
+            animation.play(AnimationClip::Walk);
+        }
+    }
+}
+
 fn enemies_damage_towers (
     mut commands: Commands,
-    mut enemies: Query<(&Transform, &mut Damage)>,
+    mut enemies: Query<(&Transform, &Walking, &mut Damage, &mut EnemyState, Option<&mut Vaulter>, Entity), Without<Flying>>,
     mut towers: Query<(&Transform, &mut towers::Health)>,
 ) {
-    for (transform, mut enemy) in enemies.iter_mut() {
+    for (transform, walking, mut enemy, mut state, mut vaulter, entity) in enemies.iter_mut() {
         enemy.colliding = false;
+        if *state != EnemyState::Walking {
+            continue;
+        }
         for (tower_transform, mut tower ) in &mut towers {
             if transform.translation.y == tower_transform.translation.y && (transform.translation.x - tower_transform.translation.x).abs() < 30.0 {
+                if let Some(vaulter) = vaulter.as_mut().filter(|vaulter| !vaulter.vaulted) {
+                    vaulter.vaulted = true;
+                    *state = EnemyState::Vaulting {
+                        from_x: transform.translation.x,
+                        to_x: tower_transform.translation.x + walking.direction * GRID_X_SPACING / 2.0,
+                        elapsed: 0.0,
+                    };
+                    commands.entity(entity).insert(Untargetable);
+                    break;
+                }
                 tower.health -= enemy.damage;
                 enemy.colliding = true;
             }
         }
     } 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digger_walks_through_an_empty_lane_and_leaves() {
+        let mut app = App::new();
+        app
+            .insert_resource(FixedTime::new_from_secs(0.5))
+            .insert_resource(generate_board((0, 0)))
+            .insert_resource(Tilesheet(Handle::default()))
+            .add_systems(Startup, |mut commands: Commands, tilesheet: Res<Tilesheet>| {
+                let position = Vec3::new(ENEMY_SPAWN_X, convert_lane_to_world_y(0), 0.0);
+                spawn_enemy(&mut commands, &tilesheet, Enemies::Digger, position);
+            })
+            .add_systems(Update, (walk_enemies, surface_diggers, apply_deferred, despawn_escaped_diggers).chain());
+
+        let left_edge = app.world.resource::<Board>().left_edge_x();
+        let mut surfaced = false;
+        for _ in 0..1000 {
+            app.update();
+            let mut diggers = app.world.query_filtered::<(&Transform, &EnemyState, &Walking), With<Digger>>();
+            let Some((transform, state, walking)) = diggers.iter(&app.world).next() else {
+                break;
+            };
+            assert!(transform.translation.x > left_edge, "digger crossed the breach line");
+            if *state == EnemyState::Walking {
+                assert_eq!(walking.direction, 1.0);
+                surfaced = true;
+            }
+        }
+        assert!(surfaced, "digger never surfaced");
+        assert_eq!(app.world.query::<&EnemyHealth>().iter(&app.world).count(), 0, "digger never left the board");
+    }
+}
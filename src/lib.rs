@@ -0,0 +1,34 @@
+use bevy::prelude::*;
+
+pub mod towers;
+pub mod board;
+pub mod ui;
+pub mod enemies;
+pub mod animation;
+pub mod pickups;
+pub mod rng;
+pub mod mowers;
+pub mod run;
+pub mod rewards;
+pub mod relics;
+pub mod modifiers;
+pub mod save;
+pub mod resume;
+pub mod actions;
+pub mod sim;
+pub mod speed;
+#[cfg(test)]
+mod testing;
+
+#[derive(Component)]
+pub struct MainCamera;
+
+#[derive(States, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum GameState {
+    #[default]
+    Map,
+    Battle,
+    Reward,
+    GameOver,
+    Victory,
+}
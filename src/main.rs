@@ -1,19 +1,29 @@
 use bevy::{prelude::*, render::camera::{ScalingMode}, ecs::query};
 
-mod towers;
-mod board;
-mod ui;
-mod enemies;
-
-use board::*;
-
-
-#[derive(Component)]
-pub struct MainCamera;
+use pvz_rogue::{*, board::*};
 
 
 fn main() {
-    App::new()
+    let replay = actions::replay_from_args();
+    let seed = replay.as_ref().map_or_else(rand::random, |replay| replay.seed);
+    let tick_rate = match &replay {
+        Some(replay) => replay.tick_rate,
+        None => sim::tick_rate_from_args().unwrap_or(sim::DEFAULT_TICK_RATE),
+    };
+    let replay_mode = match replay {
+        Some(replay) => actions::ReplayMode::Playing { replay, next: 0 },
+        None => actions::ReplayMode::Recording(actions::Replay {
+            content_version: actions::CONTENT_VERSION,
+            seed,
+            tick_rate,
+            // Filled in when the run starts.
+            meta: save::MetaSave::default(),
+            actions: Vec::new(),
+        }),
+    };
+
+    let mut app = App::new();
+    app
         .add_plugins(
             DefaultPlugins
                 .set(ImagePlugin::default_linear())
@@ -28,13 +38,29 @@ fn main() {
                 })
                 .build(),
         )
-        .add_plugins((towers::TowerPlugin, enemies::EnemiesPlugin))
+        .add_state::<GameState>()
+        .add_plugins((sim::SimPlugin { tick_rate }, actions::ActionPlugin))
+        .add_plugins((animation::AnimationPlugin, mowers::MowerPlugin, run::RunPlugin, rewards::RewardPlugin, save::SavePlugin, resume::ResumePlugin, speed::SpeedPlugin, towers::TowerPlugin, enemies::EnemiesPlugin, pickups::PickupPlugin))
         .insert_resource(towers::Money(500))
-        .insert_resource(board::generate_board())
-        .add_systems(Startup, (setup, ui::spawn_ui))
-        .add_systems(Update, (ui::update_money, ))
-        .add_systems(Update, gizmos_grid)
-        .run();
+        .insert_resource(board::generate_board((-4, 2)))
+        .insert_resource(rng::RunRng::from_seed(seed))
+        .insert_resource(pickups::SkyDrops::day())
+        .add_systems(Startup, (setup, ui::spawn_ui, ui::spawn_relic_strip))
+        .add_systems(Update, (ui::update_money, ui::animate_floating_text, ui::update_boss_health_bar, ui::update_relic_strip))
+        // Adds children and components to enemies, so it has to happen on a tick for their
+        // query order, and with it every roll of the run's rng, to replay the same.
+        .add_systems(FixedUpdate, ui::attach_health_bars.in_set(sim::SimSet::End))
+        .add_systems(Update, (
+            ui::update_health_bars, ui::trigger_damage_flash, ui::update_damage_flash, ui::spawn_damage_numbers))
+        .add_systems(OnEnter(GameState::GameOver), ui::spawn_game_over)
+        .add_systems(OnEnter(GameState::Victory), ui::spawn_victory)
+        .add_systems(Update, gizmos_grid);
+    // A replay plays back against the progress it was recorded with, not the local save.
+    if let actions::ReplayMode::Playing { replay, .. } = &replay_mode {
+        app.insert_resource(replay.meta.clone());
+    }
+    app.insert_resource(replay_mode);
+    app.run();
 }
 
 
@@ -0,0 +1,98 @@
+use crate::{run::Run, towers::Tower};
+
+/// A number the run's upgrades and relics can change. Systems ask for the modified
+/// value through `modify` instead of reading the raw stat off a component.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Stat {
+    TowerCost(Tower),
+    TowerHealth,
+    Payout,
+    ProjectileDamage,
+    StartingMoney,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Modifier {
+    pub stat: Stat,
+    /// Added to the base value before `percent` is applied.
+    pub flat: f32,
+    /// Summed across all modifiers of a stat, so two +10% give +20%.
+    pub percent: f32,
+}
+
+impl Modifier {
+    pub fn flat(stat: Stat, flat: f32) -> Self {
+        Modifier { stat, flat, percent: 0.0 }
+    }
+
+    pub fn percent(stat: Stat, percent: f32) -> Self {
+        Modifier { stat, flat: 0.0, percent }
+    }
+}
+
+/// Every modifier active in the run.
+pub fn active_modifiers(run: &Run) -> impl Iterator<Item = Modifier> + '_ {
+    run.upgrades.iter()
+        .flat_map(|upgrade| upgrade.modifiers())
+        .chain(run.relics.iter().flat_map(|relic| relic.modifiers()))
+}
+
+pub fn modify(run: &Run, stat: Stat, base: f32) -> f32 {
+    let (flat, percent) = active_modifiers(run)
+        .filter(|modifier| modifier.stat == stat)
+        .fold((0.0, 0.0), |(flat, percent), modifier| (flat + modifier.flat, percent + modifier.percent));
+    ((base + flat) * (1.0 + percent)).max(0.0)
+}
+
+pub fn modify_u32(run: &Run, stat: Stat, base: u32) -> u32 {
+    modify(run, stat, base as f32).round() as u32
+}
+
+pub fn modify_i32(run: &Run, stat: Stat, base: i32) -> i32 {
+    modify(run, stat, base as f32).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{relics::Relic, rewards::Upgrade};
+
+    fn run_with(upgrades: &[Upgrade], relics: &[Relic]) -> Run {
+        Run { nodes: Vec::new(), current: None, gold: 0, rest_money: 0, upgrades: upgrades.to_vec(), relics: relics.to_vec(), ascension: 0 }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn flat_is_added_before_percent() {
+        let run = run_with(&[Upgrade::MoneyPayout, Upgrade::MoneyPayout], &[Relic::GoldenPig]);
+        assert_close(modify(&run, Stat::Payout, 25.0), (25.0 + 20.0) * 1.2);
+        assert_eq!(modify_u32(&run, Stat::Payout, 25), 54);
+    }
+
+    #[test]
+    fn percents_add_up_instead_of_compounding() {
+        let run = run_with(&[Upgrade::ProjectileDamage, Upgrade::ProjectileDamage], &[Relic::SharpenedPeas]);
+        assert_close(modify(&run, Stat::ProjectileDamage, 10.0), 16.0);
+        assert_eq!(modify_i32(&run, Stat::ProjectileDamage, 10), 16);
+    }
+
+    #[test]
+    fn modifiers_only_touch_their_stat() {
+        let run = run_with(&[Upgrade::TowerHealth], &[Relic::PiggyDiscount]);
+        assert_close(modify(&run, Stat::TowerCost(Tower::NormalProjectile), 100.0), 100.0);
+        assert_close(modify(&run, Stat::Payout, 25.0), 25.0);
+        assert_close(modify(&run, Stat::TowerHealth, 100.0), 125.0);
+        assert_close(modify(&run_with(&[], &[]), Stat::TowerHealth, 100.0), 100.0);
+    }
+
+    #[test]
+    fn result_is_clamped_at_zero() {
+        let run = run_with(&[], &[Relic::PiggyDiscount]);
+        assert_close(modify(&run, Stat::TowerCost(Tower::Money), 50.0), 25.0);
+        assert_close(modify(&run, Stat::TowerCost(Tower::Money), 10.0), 0.0);
+        assert_eq!(modify_u32(&run, Stat::TowerCost(Tower::Money), 10), 0);
+    }
+}
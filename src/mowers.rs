@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+
+use crate::{GameState, animation::Tilesheet, run, board::*, enemies::{EnemyHealth, EnemyState, Lane, Walking}, sim::{GameplaySet, Interpolated}};
+
+const MOWER_TILE: usize = 268;
+const MOWER_SPEED: f32 = 500.0;
+/// How close an enemy has to be to the mower to get run over.
+const MOWER_REACH: f32 = 40.0;
+/// Mowers that drive past this x have left the board.
+const MOWER_END_X: f32 = 650.0;
+
+/// Sent when a mower runs over an enemy.
+#[derive(Event)]
+pub struct MowerKill {
+    pub target: Entity,
+    pub lane: i32,
+}
+
+/// Last line of defense at the left end of `lane`. Its state lives on the `Board`.
+#[derive(Component)]
+pub struct Mower {
+    pub lane: i32,
+}
+
+pub struct MowerPlugin;
+
+impl Plugin for MowerPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<MowerKill>()
+            .add_systems(OnEnter(GameState::Battle), spawn_mowers.after(run::start_battle))
+            .add_systems(FixedUpdate, (check_breaches, sweep_mowers)
+                .chain()
+                .in_set(GameplaySet::Combat)
+                .run_if(in_state(GameState::Battle)));
+    }
+}
+
+pub fn spawn_mowers(mut commands: Commands, tilesheet: Res<Tilesheet>, board: Res<Board>) {
+    let x = board.left_edge_x() - GRID_X_SPACING / 2.0;
+    for &lane in board.mowers.keys() {
+        let position = Vec3::new(x, convert_lane_to_world_y(lane), 1.0);
+        commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: tilesheet.0.clone(),
+                sprite: TextureAtlasSprite {
+                    index: MOWER_TILE,
+                    color: Color::LIME_GREEN,
+                    ..default()
+                },
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            Mower { lane },
+            Interpolated::at(position),
+        ));
+    }
+}
+
+/// The first enemy to reach the left edge of a lane starts its mower; the next one ends the run.
+/// Burrowing diggers and anything walking back right are on their way out, not in.
+fn check_breaches(
+    mut board: ResMut<Board>,
+    mut next_state: ResMut<NextState<GameState>>,
+    enemies: Query<(&Transform, &Lane, &EnemyHealth, &Walking, &EnemyState)>,
+) {
+    let left_edge = board.left_edge_x();
+    for (transform, lane, health, walking, state) in enemies.iter() {
+        if health.health <= 0
+            || transform.translation.x > left_edge
+            || *state == EnemyState::Burrowing
+            || walking.direction > 0.0
+        {
+            continue;
+        }
+        match board.mowers.get(&lane.0) {
+            Some(MowerState::Ready) => {
+                board.mowers.insert(lane.0, MowerState::Sweeping);
+            }
+            Some(MowerState::Sweeping) => {}
+            Some(MowerState::Used) | None => next_state.set(GameState::GameOver),
+        }
+    }
+}
+
+fn sweep_mowers(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut board: ResMut<Board>,
+    mut mower_kills: EventWriter<MowerKill>,
+    mut mowers: Query<(&Mower, &mut Transform, Entity)>,
+    mut enemies: Query<(&Transform, &Lane, &mut EnemyHealth, Entity), Without<Mower>>,
+) {
+    for (mower, mut transform, entity) in mowers.iter_mut() {
+        if board.mowers.get(&mower.lane) != Some(&MowerState::Sweeping) {
+            continue;
+        }
+        transform.translation.x += MOWER_SPEED * time.period.as_secs_f32();
+        for (enemy_transform, lane, mut health, enemy) in enemies.iter_mut() {
+            if lane.0 == mower.lane
+                && health.health > 0
+                && enemy_transform.translation.x - transform.translation.x < MOWER_REACH
+            {
+                health.health = 0;
+                mower_kills.send(MowerKill { target: enemy, lane: lane.0 });
+            }
+        }
+        if transform.translation.x > MOWER_END_X {
+            board.mowers.insert(mower.lane, MowerState::Used);
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use bevy::{input::InputSystem, prelude::*, window::PrimaryWindow};
+use rand::Rng;
+
+use crate::{GameState, MainCamera, actions::{ActionQueue, ActionSet, PlayerAction}, board::*, rng::RunRng, sim::{GameplaySet, Interpolated}, speed::GameSpeed, towers::{Money, MoneyUpdated}, ui};
+
+const COIN_TEXTURE: &str = "kenney/PNG/DefaultSize/towerDefense_tile287.png";
+const COIN_RADIUS: f32 = 30.0;
+const COIN_LIFETIME: f32 = 8.0;
+const MAGNET_SPEED: f32 = 600.0;
+const SKY_DROP_HEIGHT: f32 = 400.0;
+const SKY_DROP_SPEED: f32 = 80.0;
+/// Where magnetised coins fly to, next to the money counter.
+const MAGNET_TARGET: Vec3 = Vec3::new(-600.0, 330.0, 0.0);
+
+/// Money lying on the board. It is only credited once clicked, or when its lifetime runs out.
+#[derive(Component)]
+pub struct Coin {
+    pub value: u32,
+    pub lifetime: Timer,
+}
+
+/// A coin still falling from the sky towards `target_y`. It doesn't expire until it lands.
+#[derive(Component)]
+pub struct Falling {
+    pub target_y: f32,
+}
+
+/// Baseline income that drops coins onto random board cells. Levels configure it;
+/// night levels turn it off.
+#[derive(Resource)]
+pub struct SkyDrops {
+    pub enabled: bool,
+    pub value: u32,
+    pub timer: Timer,
+}
+
+impl SkyDrops {
+    pub fn day() -> Self {
+        SkyDrops {
+            enabled: true,
+            value: 25,
+            timer: Timer::from_seconds(10.0, TimerMode::Repeating),
+        }
+    }
+
+    pub fn night() -> Self {
+        SkyDrops {
+            enabled: false,
+            ..SkyDrops::day()
+        }
+    }
+}
+
+/// Upgrade that pulls every coin into the money counter without clicking.
+#[derive(Resource, Default)]
+pub struct CoinMagnet(pub bool);
+
+pub struct PickupPlugin;
+
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<CoinMagnet>()
+            // Runs right after input is read so a click on a coin never also places a tower.
+            .add_systems(PreUpdate, click_coins.after(InputSystem)
+                .run_if(in_state(GameState::Battle).and_then(any_with_component::<PrimaryWindow>())))
+            .add_systems(FixedUpdate, collect_clicked_coins.in_set(ActionSet::Handle).run_if(in_state(GameState::Battle)))
+            .add_systems(FixedUpdate, (
+                (expire_coins, magnet_coins, spawn_sky_drops).chain().in_set(GameplaySet::Act),
+                fall_coins.in_set(GameplaySet::Move))
+                .run_if(in_state(GameState::Battle)));
+    }
+}
+
+pub fn spawn_coin(commands: &mut Commands, asset_server: &Res<AssetServer>, value: u32, position: Vec3) -> Entity {
+    let position = Vec3::new(position.x, position.y, 5.0);
+    commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load(COIN_TEXTURE),
+            sprite: Sprite {
+                color: Color::GOLD,
+                ..default()
+            },
+            transform: Transform::from_translation(position),
+            ..default()
+        },
+        Coin {
+            value,
+            lifetime: Timer::from_seconds(COIN_LIFETIME, TimerMode::Once),
+        },
+        Interpolated::at(position),
+    )).id()
+}
+
+fn collect_coin(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    money: &mut ResMut<Money>,
+    money_updated: &mut EventWriter<MoneyUpdated>,
+    coin: &Coin,
+    position: Vec3,
+    entity: Entity,
+) {
+    money.0 += coin.value;
+    money_updated.send(MoneyUpdated {
+        new_value: money.0
+    });
+    ui::spawn_floating_text(commands, asset_server, format!("+{}", coin.value), position, Color::GOLD);
+    commands.entity(entity).despawn();
+}
+
+pub fn click_coins(
+    mut input: ResMut<Input<MouseButton>>,
+    mut actions: ResMut<ActionQueue>,
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    coins: Query<&Transform, With<Coin>>,
+    speed: Res<GameSpeed>,
+) {
+    if speed.paused || !input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (primary_query.get_single(), camera_q.get_single()) else {
+        return;
+    };
+    let Some(world_position) = window.cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .map(|ray| ray.origin.truncate())
+    else {
+        return;
+    };
+    if coins.iter().any(|transform| transform.translation.truncate().distance(world_position) < COIN_RADIUS) {
+        actions.push(PlayerAction::CollectCoin { position: world_position.to_array() });
+        input.clear_just_pressed(MouseButton::Left);
+    }
+}
+
+fn collect_clicked_coins(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    asset_server: Res<AssetServer>,
+    mut money: ResMut<Money>,
+    mut money_updated: EventWriter<MoneyUpdated>,
+    coins: Query<(&Coin, &Transform, Entity)>,
+) {
+    for action in actions.iter() {
+        let PlayerAction::CollectCoin { position } = *action else {
+            continue;
+        };
+        let clicked = coins.iter()
+            .find(|(_, transform, _)| transform.translation.truncate().distance(Vec2::from_array(position)) < COIN_RADIUS);
+        if let Some((coin, transform, entity)) = clicked {
+            collect_coin(&mut commands, &asset_server, &mut money, &mut money_updated, coin, transform.translation, entity);
+        }
+    }
+}
+
+fn expire_coins(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<FixedTime>,
+    mut money: ResMut<Money>,
+    mut money_updated: EventWriter<MoneyUpdated>,
+    mut coins: Query<(&mut Coin, &Transform, Entity), Without<Falling>>,
+) {
+    for (mut coin, transform, entity) in &mut coins {
+        coin.lifetime.tick(time.period);
+        if coin.lifetime.just_finished() {
+            collect_coin(&mut commands, &asset_server, &mut money, &mut money_updated, &coin, transform.translation, entity);
+        }
+    }
+}
+
+fn magnet_coins(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<FixedTime>,
+    magnet: Res<CoinMagnet>,
+    mut money: ResMut<Money>,
+    mut money_updated: EventWriter<MoneyUpdated>,
+    mut coins: Query<(&Coin, &mut Transform, Entity)>,
+) {
+    if !magnet.0 {
+        return;
+    }
+    for (coin, mut transform, entity) in &mut coins {
+        // Already collected by `expire_coins` this frame.
+        if coin.lifetime.finished() {
+            continue;
+        }
+        let target = Vec3::new(MAGNET_TARGET.x, MAGNET_TARGET.y, transform.translation.z);
+        let step = MAGNET_SPEED * time.period.as_secs_f32();
+        if transform.translation.distance(target) <= step {
+            collect_coin(&mut commands, &asset_server, &mut money, &mut money_updated, coin, transform.translation, entity);
+        } else {
+            let direction = (target - transform.translation).normalize();
+            transform.translation += direction * step;
+        }
+    }
+}
+
+fn spawn_sky_drops(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<FixedTime>,
+    board: Res<Board>,
+    mut sky_drops: ResMut<SkyDrops>,
+    mut run_rng: ResMut<RunRng>,
+) {
+    if !sky_drops.enabled {
+        return;
+    }
+    sky_drops.timer.tick(time.period);
+    if !sky_drops.timer.just_finished() {
+        return;
+    }
+    // Sorted so the same seed always picks the same cell, whatever the HashMap order.
+    let mut cells: Vec<(i32, i32)> = board.towers.keys().copied().collect();
+    cells.sort();
+    if cells.is_empty() {
+        return;
+    }
+    let cell = cells[run_rng.rng.gen_range(0..cells.len())];
+    let target = convert_grid_to_world(cell);
+    let coin = spawn_coin(&mut commands, &asset_server, sky_drops.value, Vec3::new(target.x, SKY_DROP_HEIGHT, 0.0));
+    commands.entity(coin).insert(Falling { target_y: target.y });
+}
+
+fn fall_coins(
+    mut commands: Commands,
+    time: Res<FixedTime>,
+    mut coins: Query<(&Falling, &mut Transform, Entity)>,
+) {
+    for (falling, mut transform, entity) in &mut coins {
+        transform.translation.y -= SKY_DROP_SPEED * time.period.as_secs_f32();
+        if transform.translation.y <= falling.target_y {
+            transform.translation.y = falling.target_y;
+            commands.entity(entity).remove::<Falling>();
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{modifiers::{Modifier, Stat}, towers::Tower};
+
+/// Run-long passive effects picked up from reward drafts.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Relic {
+    /// Pulls every coin into the money counter without clicking.
+    CoinMagnet,
+    SharpenedPeas,
+    PiggyDiscount,
+    WarChest,
+    GoldenPig,
+}
+
+impl Relic {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Relic::CoinMagnet => "Coin Magnet",
+            Relic::SharpenedPeas => "Sharpened Peas",
+            Relic::PiggyDiscount => "Piggy Discount",
+            Relic::WarChest => "War Chest",
+            Relic::GoldenPig => "Golden Pig",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Relic::CoinMagnet => "Coins collect themselves",
+            Relic::SharpenedPeas => "+10% projectile damage",
+            Relic::PiggyDiscount => "Money towers cost 25 less",
+            Relic::WarChest => "Start each battle with +100 money",
+            Relic::GoldenPig => "+20% money from payouts",
+        }
+    }
+
+    pub fn modifiers(&self) -> Vec<Modifier> {
+        match self {
+            Relic::CoinMagnet => vec![],
+            Relic::SharpenedPeas => vec![Modifier::percent(Stat::ProjectileDamage, 0.1)],
+            Relic::PiggyDiscount => vec![Modifier::flat(Stat::TowerCost(Tower::Money), -25.0)],
+            Relic::WarChest => vec![Modifier::flat(Stat::StartingMoney, 100.0)],
+            Relic::GoldenPig => vec![Modifier::percent(Stat::Payout, 0.2)],
+        }
+    }
+}
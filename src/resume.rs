@@ -0,0 +1,504 @@
+use std::fs;
+
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*, utils::Duration};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GameState,
+    actions::{not_replaying, SimTick},
+    animation::Tilesheet,
+    board::*,
+    enemies::{
+        self, Armor, Boss, EnemyArchetype, EnemyHealth, EnemyProjectile, EnemyState, Enemies, Lane, LaneSwitcher, Ranged,
+        SpawnTimer, Summoner, Untargetable, Vaulter, Walking,
+    },
+    mowers::{self, Mower},
+    pickups::{self, Coin, CoinMagnet, Falling, SkyDrops},
+    rewards::{Reward, RewardDraft},
+    rng::RunRng,
+    run::{BattleConfig, Run},
+    save,
+    sim,
+    towers::{self, DamageType, Health, Money, MoneyGain, MoneyTowerAnimation, MoneyUpdated, PlacedTower, Projectile, ProjectileTower, SelectableTowers, Tower},
+};
+
+const RUN_FILE: &str = "run.json";
+/// Mid-run saves from another version are dropped rather than migrated.
+const RUN_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TowerSave {
+    pub id: TowerId,
+    pub tower: Tower,
+    pub cell: (i32, i32),
+    pub health: i32,
+    pub max_health: i32,
+    /// Progress of the tower's shoot or payout timer.
+    pub timer_elapsed: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnemySave {
+    pub archetype: Enemies,
+    pub position: [f32; 3],
+    /// Diggers turn around when they surface.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub lane: i32,
+    pub health: i32,
+    pub max_health: i32,
+    pub speed: f32,
+    pub direction: f32,
+    pub state: EnemyState,
+    pub untargetable: bool,
+    pub armor: Option<i32>,
+    pub boss_phase: Option<usize>,
+    pub vaulted: Option<bool>,
+    pub switches_left: Option<u32>,
+    pub summon_elapsed: Option<f32>,
+    pub shoot_elapsed: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProjectileSave {
+    pub position: [f32; 3],
+    pub speed: f32,
+    pub damage: i32,
+    pub damage_type: DamageType,
+    pub anti_air: bool,
+    pub direction: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnemyProjectileSave {
+    pub position: [f32; 3],
+    pub speed: f32,
+    pub damage: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CoinSave {
+    pub position: [f32; 3],
+    pub value: u32,
+    pub lifetime_elapsed: f32,
+    pub falling_to: Option<f32>,
+}
+
+/// Everything on the board mid-battle.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BattleSave {
+    pub config: BattleConfig,
+    pub money: u32,
+    pub next_tower_id: u32,
+    pub mowers: Vec<(i32, MowerState)>,
+    pub towers: Vec<TowerSave>,
+    pub enemies: Vec<EnemySave>,
+    pub projectiles: Vec<ProjectileSave>,
+    pub enemy_projectiles: Vec<EnemyProjectileSave>,
+    pub coins: Vec<CoinSave>,
+    pub wave_index: usize,
+    pub wave_elapsed: f32,
+    pub wave_duration: f32,
+    pub sky_drop_elapsed: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum SavedScreen {
+    Map,
+    Reward(Vec<Reward>),
+    Battle(BattleSave),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RunSave {
+    pub version: u32,
+    /// Simulation tick the save was made on.
+    pub tick: u64,
+    pub run: Run,
+    pub rng: RunRng,
+    pub towers: Vec<Tower>,
+    pub coin_magnet: bool,
+    pub screen: SavedScreen,
+}
+
+/// A battle loaded from a run save, put back on the board once the battle has started.
+#[derive(Resource)]
+pub struct PendingBattle(pub BattleSave);
+
+pub fn load_run() -> Option<RunSave> {
+    let path = save::data_dir().join(RUN_FILE);
+    let contents = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<RunSave>(&contents) {
+        Ok(save) if save.version == RUN_VERSION => Some(save),
+        Ok(save) => {
+            warn!("dropping {} from run save version {}", path.display(), save.version);
+            None
+        }
+        Err(err) => {
+            warn!("could not read {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+pub fn delete_run() {
+    let _ = fs::remove_file(save::data_dir().join(RUN_FILE));
+}
+
+pub struct ResumePlugin;
+
+impl Plugin for ResumePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Before the first tick, so nothing ever runs on the fresh board.
+            .add_systems(OnEnter(GameState::Battle), (apply_deferred, restore_battle.run_if(resource_exists::<PendingBattle>()))
+                .chain()
+                .after(enemies::start_wave)
+                .after(mowers::spawn_mowers))
+            .add_systems(OnEnter(GameState::Map), save_run.run_if(not_replaying))
+            .add_systems(OnEnter(GameState::GameOver), delete_run.run_if(not_replaying))
+            .add_systems(OnEnter(GameState::Victory), delete_run.run_if(not_replaying))
+            .add_systems(Last, (sim::snap_to_sim, save_run).chain().run_if(not_replaying.and_then(on_event::<AppExit>())));
+    }
+}
+
+#[derive(SystemParam)]
+pub struct BattleQueries<'w, 's> {
+    board: Res<'w, Board>,
+    money: Res<'w, Money>,
+    sky_drops: Res<'w, SkyDrops>,
+    spawn_timers: Query<'w, 's, &'static SpawnTimer>,
+    towers: Query<'w, 's, (&'static PlacedTower, &'static Health, Option<&'static MoneyGain>, Option<&'static ProjectileTower>)>,
+    enemies: Query<'w, 's, (
+        (&'static EnemyArchetype, &'static Transform, &'static Lane, &'static EnemyHealth, &'static Walking, &'static EnemyState),
+        (Option<&'static Untargetable>, Option<&'static Armor>, Option<&'static Boss>, Option<&'static Vaulter>),
+        (Option<&'static LaneSwitcher>, Option<&'static Summoner>, Option<&'static Ranged>),
+    )>,
+    projectiles: Query<'w, 's, (&'static Transform, &'static Projectile)>,
+    enemy_projectiles: Query<'w, 's, (&'static Transform, &'static EnemyProjectile)>,
+    coins: Query<'w, 's, (&'static Transform, &'static Coin, Option<&'static Falling>)>,
+}
+
+impl BattleQueries<'_, '_> {
+    fn snapshot(&self, config: &BattleConfig) -> BattleSave {
+        let cell_of = |id: TowerId| self.board.towers.iter()
+            .find(|(_, tower)| **tower == Some(id))
+            .map(|(cell, _)| *cell);
+        let spawn_timer = self.spawn_timers.iter().next();
+        BattleSave {
+            config: config.clone(),
+            money: self.money.0,
+            next_tower_id: self.board.next_tower_id,
+            // A mower mid-sweep has done its job by the time the battle is resumed.
+            mowers: self.board.mowers.iter()
+                .map(|(lane, state)| (*lane, if *state == MowerState::Ready { MowerState::Ready } else { MowerState::Used }))
+                .collect(),
+            towers: self.towers.iter()
+                .filter_map(|(placed, health, money_gain, projectile_tower)| Some(TowerSave {
+                    id: placed.id,
+                    tower: placed.tower,
+                    cell: cell_of(placed.id)?,
+                    health: health.health,
+                    max_health: health.max,
+                    timer_elapsed: money_gain.map(|gain| gain.gaintimer.elapsed_secs())
+                        .or(projectile_tower.map(|tower| tower.shoot_timer.elapsed_secs()))
+                        .unwrap_or(0.0),
+                }))
+                .collect(),
+            enemies: self.enemies.iter()
+                .filter(|((_, _, _, health, ..), ..)| health.health > 0)
+                .map(|(
+                    (archetype, transform, lane, health, walking, state),
+                    (untargetable, armor, boss, vaulter),
+                    (switcher, summoner, ranged),
+                )| EnemySave {
+                    archetype: archetype.0,
+                    position: transform.translation.to_array(),
+                    rotation: transform.rotation.to_array(),
+                    scale: transform.scale.to_array(),
+                    lane: lane.0,
+                    health: health.health,
+                    max_health: health.max,
+                    speed: walking.speed,
+                    direction: walking.direction,
+                    state: *state,
+                    untargetable: untargetable.is_some(),
+                    armor: armor.map(|armor| armor.health),
+                    boss_phase: boss.map(|boss| boss.next_phase),
+                    vaulted: vaulter.map(|vaulter| vaulter.vaulted),
+                    switches_left: switcher.map(|switcher| switcher.switches_left),
+                    summon_elapsed: summoner.map(|summoner| summoner.interval.elapsed_secs()),
+                    shoot_elapsed: ranged.map(|ranged| ranged.shoot_timer.elapsed_secs()),
+                })
+                .collect(),
+            projectiles: self.projectiles.iter()
+                .map(|(transform, projectile)| ProjectileSave {
+                    position: transform.translation.to_array(),
+                    speed: projectile.speed,
+                    damage: projectile.damage,
+                    damage_type: projectile.damage_type,
+                    anti_air: projectile.anti_air,
+                    direction: projectile.direction,
+                })
+                .collect(),
+            enemy_projectiles: self.enemy_projectiles.iter()
+                .map(|(transform, projectile)| EnemyProjectileSave {
+                    position: transform.translation.to_array(),
+                    speed: projectile.speed,
+                    damage: projectile.damage,
+                })
+                .collect(),
+            coins: self.coins.iter()
+                .map(|(transform, coin, falling)| CoinSave {
+                    position: transform.translation.to_array(),
+                    value: coin.value,
+                    lifetime_elapsed: coin.lifetime.elapsed_secs(),
+                    falling_to: falling.map(|falling| falling.target_y),
+                })
+                .collect(),
+            wave_index: spawn_timer.map_or(0, |timer| timer.index),
+            wave_elapsed: spawn_timer.map_or(0.0, |timer| timer.timer.elapsed_secs()),
+            wave_duration: spawn_timer.map_or(0.0, |timer| timer.timer.duration().as_secs_f32()),
+            sky_drop_elapsed: self.sky_drops.timer.elapsed_secs(),
+        }
+    }
+}
+
+/// Saves the run between screens and when the game is closed. Finished runs have no save.
+fn save_run(
+    state: Res<State<GameState>>,
+    run: Res<Run>,
+    run_rng: Res<RunRng>,
+    towers: Res<SelectableTowers>,
+    magnet: Res<CoinMagnet>,
+    draft: Res<RewardDraft>,
+    battle: Option<Res<BattleConfig>>,
+    pending: Option<Res<PendingBattle>>,
+    tick: Res<SimTick>,
+    battle_queries: BattleQueries,
+) {
+    // The saved battle hasn't been put back yet; the file on disk is still the newest state.
+    if pending.is_some() {
+        return;
+    }
+    let screen = match (state.get(), battle) {
+        (GameState::Map, _) => SavedScreen::Map,
+        (GameState::Reward, _) => SavedScreen::Reward(draft.0.clone()),
+        (GameState::Battle, Some(battle)) => SavedScreen::Battle(battle_queries.snapshot(&battle)),
+        _ => return,
+    };
+    save::write_json(RUN_FILE, &RunSave {
+        version: RUN_VERSION,
+        tick: tick.0,
+        run: run.clone(),
+        rng: run_rng.clone(),
+        towers: towers.possible_towers.iter().map(|tower| tower.tower_type).collect(),
+        coin_magnet: magnet.0,
+        screen,
+    });
+}
+
+/// Runs after the battle's own setup, replacing the fresh board with the saved one.
+fn restore_battle(
+    mut commands: Commands,
+    pending: Res<PendingBattle>,
+    asset_server: Res<AssetServer>,
+    tilesheet: Res<Tilesheet>,
+    money_tower_animation: Res<MoneyTowerAnimation>,
+    mut board: ResMut<Board>,
+    mut money: ResMut<Money>,
+    mut money_updated: EventWriter<MoneyUpdated>,
+    mut sky_drops: ResMut<SkyDrops>,
+    mut spawn_timers: Query<&mut SpawnTimer>,
+    mowers: Query<(&Mower, Entity)>,
+) {
+    let saved = &pending.0;
+    commands.remove_resource::<PendingBattle>();
+
+    money.0 = saved.money;
+    money_updated.send(MoneyUpdated {
+        new_value: money.0
+    });
+    sky_drops.timer.set_elapsed(Duration::from_secs_f32(saved.sky_drop_elapsed));
+    for mut timer in spawn_timers.iter_mut() {
+        timer.index = saved.wave_index;
+        timer.timer.set_duration(Duration::from_secs_f32(saved.wave_duration));
+        timer.timer.set_elapsed(Duration::from_secs_f32(saved.wave_elapsed));
+    }
+
+    board.next_tower_id = saved.next_tower_id;
+    board.mowers = saved.mowers.iter().copied().collect();
+    for (mower, entity) in &mowers {
+        if board.mowers.get(&mower.lane) != Some(&MowerState::Ready) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for tower in &saved.towers {
+        let ent = towers::spawn_tower(
+            &mut commands, convert_grid_to_world(tower.cell), &towers::buyable_tower(tower.tower),
+            &asset_server, &tilesheet, &money_tower_animation,
+        );
+        commands.entity(ent).insert((
+            PlacedTower { id: tower.id, tower: tower.tower },
+            Health { health: tower.health, max: tower.max_health },
+        ));
+        board.towers.insert(tower.cell, Some(tower.id));
+        let elapsed = Duration::from_secs_f32(tower.timer_elapsed);
+        commands.add(move |world: &mut World| {
+            if let Some(mut money_gain) = world.get_mut::<MoneyGain>(ent) {
+                money_gain.gaintimer.set_elapsed(elapsed);
+            }
+            if let Some(mut projectile_tower) = world.get_mut::<ProjectileTower>(ent) {
+                projectile_tower.shoot_timer.set_elapsed(elapsed);
+            }
+        });
+    }
+
+    for enemy in &saved.enemies {
+        let position = Vec3::from_array(enemy.position);
+        let ent = enemies::spawn_enemy(&mut commands, &tilesheet, enemy.archetype, position);
+        commands.entity(ent).insert((
+            Transform {
+                translation: position,
+                rotation: Quat::from_array(enemy.rotation),
+                scale: Vec3::from_array(enemy.scale),
+            },
+            Lane(enemy.lane),
+            EnemyHealth { health: enemy.health, max: enemy.max_health },
+            Walking { speed: enemy.speed, direction: enemy.direction },
+            enemy.state,
+        ));
+        if enemy.untargetable {
+            commands.entity(ent).insert(Untargetable);
+        } else {
+            commands.entity(ent).remove::<Untargetable>();
+        }
+        let enemy = enemy.clone();
+        commands.add(move |world: &mut World| {
+            let mut entity = world.entity_mut(ent);
+            if let (Some(health), Some(mut armor)) = (enemy.armor, entity.get_mut::<Armor>()) {
+                armor.health = health;
+            }
+            if let (Some(phase), Some(mut boss)) = (enemy.boss_phase, entity.get_mut::<Boss>()) {
+                boss.next_phase = phase;
+            }
+            if let (Some(vaulted), Some(mut vaulter)) = (enemy.vaulted, entity.get_mut::<Vaulter>()) {
+                vaulter.vaulted = vaulted;
+            }
+            if let (Some(switches_left), Some(mut switcher)) = (enemy.switches_left, entity.get_mut::<LaneSwitcher>()) {
+                switcher.switches_left = switches_left;
+            }
+            if let (Some(elapsed), Some(mut summoner)) = (enemy.summon_elapsed, entity.get_mut::<Summoner>()) {
+                summoner.interval.set_elapsed(Duration::from_secs_f32(elapsed));
+            }
+            if let (Some(elapsed), Some(mut ranged)) = (enemy.shoot_elapsed, entity.get_mut::<Ranged>()) {
+                ranged.shoot_timer.set_elapsed(Duration::from_secs_f32(elapsed));
+            }
+        });
+    }
+
+    for projectile in &saved.projectiles {
+        towers::spawn_projectile(&mut commands, &asset_server, Vec3::from_array(projectile.position), Projectile {
+            speed: projectile.speed,
+            damage: projectile.damage,
+            damage_type: projectile.damage_type,
+            anti_air: projectile.anti_air,
+            direction: projectile.direction,
+        });
+    }
+    for projectile in &saved.enemy_projectiles {
+        enemies::spawn_enemy_projectile(&mut commands, &asset_server, Vec3::from_array(projectile.position), EnemyProjectile {
+            speed: projectile.speed,
+            damage: projectile.damage,
+        });
+    }
+
+    for coin in &saved.coins {
+        let ent = pickups::spawn_coin(&mut commands, &asset_server, coin.value, Vec3::from_array(coin.position));
+        if let Some(target_y) = coin.falling_to {
+            commands.entity(ent).insert(Falling { target_y });
+        }
+        let elapsed = Duration::from_secs_f32(coin.lifetime_elapsed);
+        commands.add(move |world: &mut World| {
+            if let Some(mut coin) = world.get_mut::<Coin>(ent) {
+                coin.lifetime.set_elapsed(elapsed);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::SystemState, time::TimeUpdateStrategy};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        actions::{ActionQueue, PlayerAction, ReplayMode},
+        testing::{battle_app, with_data_dir},
+    };
+
+    fn snapshot(app: &mut App) -> BattleSave {
+        // Transforms are drawn between ticks until put back, the same as before saving on exit.
+        let mut snap = IntoSystem::into_system(sim::snap_to_sim);
+        snap.initialize(&mut app.world);
+        snap.run((), &mut app.world);
+        let mut state: SystemState<(BattleQueries, Res<BattleConfig>)> = SystemState::new(&mut app.world);
+        let (queries, config) = state.get(&app.world);
+        queries.snapshot(&config)
+    }
+
+    /// Puts lists of records in a fixed order, since the save follows query order, and rounds
+    /// off what a trip through `Duration` does to timer floats.
+    fn normalize(value: Value) -> Value {
+        match value {
+            Value::Array(items) => {
+                let mut items: Vec<Value> = items.into_iter().map(normalize).collect();
+                if items.iter().all(|item| item.is_object() || item.is_array()) {
+                    items.sort_by_key(|item| item.to_string());
+                }
+                Value::Array(items)
+            }
+            Value::Object(fields) => Value::Object(fields.into_iter().map(|(key, value)| (key, normalize(value))).collect()),
+            Value::Number(number) if number.is_f64() => Value::from((number.as_f64().unwrap() * 1e4).round() / 1e4),
+            value => value,
+        }
+    }
+
+    #[test]
+    fn restored_battle_matches_the_saved_one() {
+        with_data_dir("resume", |_| {
+            let mut battle = battle_app(ReplayMode::Off);
+            for update in 0..900 {
+                if update == 30 || update == 90 {
+                    let mut queue = battle.world.resource_mut::<ActionQueue>();
+                    queue.push(PlayerAction::SelectTower(0));
+                    queue.push(PlayerAction::PlaceTower { cell: (-7, update / 30 - 2) });
+                }
+                battle.update();
+            }
+            let saved = snapshot(&mut battle);
+            assert_eq!(saved.towers.len(), 2);
+            assert!(!saved.enemies.is_empty());
+
+            let mut resumed = battle_app(ReplayMode::Off);
+            resumed
+                .add_plugins(ResumePlugin)
+                .init_resource::<RewardDraft>()
+                .insert_resource(PendingBattle(saved.clone()))
+                // No ticks, so what is compared is exactly what was put back.
+                .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO));
+            resumed.update();
+            resumed.update();
+            assert!(!resumed.world.contains_resource::<PendingBattle>());
+            assert_eq!(resumed.world.resource::<SimTick>().0, 0);
+
+            let restored = snapshot(&mut resumed);
+            assert_eq!(
+                normalize(serde_json::to_value(&restored).unwrap()),
+                normalize(serde_json::to_value(&saved).unwrap()),
+            );
+        });
+    }
+}
@@ -0,0 +1,367 @@
+use lazy_static::lazy_static;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GameState,
+    actions::{ActionQueue, ActionSet, PlayerAction},
+    modifiers::{Modifier, Stat},
+    pickups::CoinMagnet,
+    relics::Relic,
+    rng::RunRng,
+    run::Run,
+    save::MetaSave,
+    towers::{self, SelectableTowers, Tower},
+};
+
+const DRAFT_SIZE: usize = 3;
+
+/// Permanent stat bonuses for every tower placed later in the run. They stack.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Upgrade {
+    /// +25% projectile damage.
+    ProjectileDamage,
+    /// +25% tower health.
+    TowerHealth,
+    /// +10 money per payout.
+    MoneyPayout,
+}
+
+impl Upgrade {
+    pub fn modifiers(&self) -> Vec<Modifier> {
+        match self {
+            Upgrade::ProjectileDamage => vec![Modifier::percent(Stat::ProjectileDamage, 0.25)],
+            Upgrade::TowerHealth => vec![Modifier::percent(Stat::TowerHealth, 0.25)],
+            Upgrade::MoneyPayout => vec![Modifier::flat(Stat::Payout, 10.0)],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Reward {
+    Tower(Tower),
+    Upgrade(Upgrade),
+    Relic(Relic),
+}
+
+impl Reward {
+    fn label(&self) -> String {
+        match self {
+            Reward::Tower(tower) => format!("New tower:\n{:?}", tower),
+            Reward::Upgrade(Upgrade::ProjectileDamage) => "Upgrade:\n+25% damage".to_string(),
+            Reward::Upgrade(Upgrade::TowerHealth) => "Upgrade:\n+25% tower health".to_string(),
+            Reward::Upgrade(Upgrade::MoneyPayout) => "Upgrade:\n+10 per payout".to_string(),
+            Reward::Relic(relic) => format!("Relic:\n{}\n{}", relic.name(), relic.description()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+}
+
+impl Rarity {
+    fn weight(&self) -> u32 {
+        match self {
+            Rarity::Common => 60,
+            Rarity::Uncommon => 30,
+            Rarity::Rare => 10,
+        }
+    }
+}
+
+pub struct RewardEntry {
+    pub reward: Reward,
+    pub rarity: Rarity,
+    /// How many times the run may own this reward. Owned-out rewards stop showing up.
+    pub max_copies: usize,
+}
+
+lazy_static! {
+    pub static ref REWARD_POOL: Vec<RewardEntry> = vec![
+        RewardEntry { reward: Reward::Tower(Tower::Money), rarity: Rarity::Common, max_copies: 1 },
+        RewardEntry { reward: Reward::Tower(Tower::NormalProjectile), rarity: Rarity::Common, max_copies: 1 },
+        RewardEntry { reward: Reward::Tower(Tower::AntiAir), rarity: Rarity::Common, max_copies: 1 },
+        RewardEntry { reward: Reward::Tower(Tower::BackwardsProjectile), rarity: Rarity::Common, max_copies: 1 },
+        RewardEntry { reward: Reward::Tower(Tower::SplashProjectile), rarity: Rarity::Uncommon, max_copies: 1 },
+        RewardEntry { reward: Reward::Tower(Tower::PiercingProjectile), rarity: Rarity::Uncommon, max_copies: 1 },
+        RewardEntry { reward: Reward::Upgrade(Upgrade::ProjectileDamage), rarity: Rarity::Common, max_copies: 5 },
+        RewardEntry { reward: Reward::Upgrade(Upgrade::TowerHealth), rarity: Rarity::Common, max_copies: 5 },
+        RewardEntry { reward: Reward::Upgrade(Upgrade::MoneyPayout), rarity: Rarity::Uncommon, max_copies: 3 },
+        RewardEntry { reward: Reward::Relic(Relic::CoinMagnet), rarity: Rarity::Rare, max_copies: 1 },
+        RewardEntry { reward: Reward::Relic(Relic::SharpenedPeas), rarity: Rarity::Uncommon, max_copies: 1 },
+        RewardEntry { reward: Reward::Relic(Relic::PiggyDiscount), rarity: Rarity::Uncommon, max_copies: 1 },
+        RewardEntry { reward: Reward::Relic(Relic::WarChest), rarity: Rarity::Rare, max_copies: 1 },
+        RewardEntry { reward: Reward::Relic(Relic::GoldenPig), rarity: Rarity::Rare, max_copies: 1 },
+    ];
+}
+
+/// The rewards on offer after the last battle.
+#[derive(Resource, Default)]
+pub struct RewardDraft(pub Vec<Reward>);
+
+#[derive(Component)]
+pub struct RewardUi;
+
+#[derive(Component)]
+pub struct RewardButton(pub usize);
+
+pub struct RewardPlugin;
+
+impl Plugin for RewardPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<RewardDraft>()
+            .add_systems(OnEnter(GameState::Reward), (draw_draft, spawn_reward_ui).chain())
+            .add_systems(OnExit(GameState::Reward), despawn_reward_ui)
+            .add_systems(Update, queue_reward_pick.run_if(in_state(GameState::Reward)))
+            .add_systems(FixedUpdate, pick_reward.in_set(ActionSet::Handle).run_if(in_state(GameState::Reward)));
+    }
+}
+
+/// Towers only show up once the meta save has unlocked them.
+fn unlocked(meta: &MetaSave, reward: Reward) -> bool {
+    match reward {
+        Reward::Tower(tower) => meta.unlocked_towers.contains(&tower),
+        _ => true,
+    }
+}
+
+fn owned(run: &Run, towers: &SelectableTowers, reward: Reward) -> usize {
+    match reward {
+        Reward::Tower(tower) => towers.possible_towers.iter().filter(|owned| owned.tower_type == tower).count(),
+        Reward::Upgrade(upgrade) => run.upgrades.iter().filter(|owned| **owned == upgrade).count(),
+        Reward::Relic(relic) => run.relics.iter().filter(|owned| **owned == relic).count(),
+    }
+}
+
+/// Draws up to `count` different rewards the run can still take, weighted by rarity.
+pub fn draw_rewards(
+    rng: &mut impl Rng, run: &Run, meta: &MetaSave, towers: &SelectableTowers, count: usize
+) -> Vec<Reward> {
+    let mut candidates: Vec<&RewardEntry> = REWARD_POOL.iter()
+        .filter(|entry| unlocked(meta, entry.reward) && owned(run, towers, entry.reward) < entry.max_copies)
+        .collect();
+    let mut draft = Vec::new();
+    while draft.len() < count && !candidates.is_empty() {
+        let total: u32 = candidates.iter().map(|entry| entry.rarity.weight()).sum();
+        let mut roll = rng.gen_range(0..total);
+        let picked = candidates.iter()
+            .position(|entry| {
+                let weight = entry.rarity.weight();
+                if roll < weight {
+                    return true;
+                }
+                roll -= weight;
+                false
+            })
+            .unwrap_or(0);
+        draft.push(candidates.swap_remove(picked).reward);
+    }
+    draft
+}
+
+fn draw_draft(
+    run: Res<Run>,
+    meta: Res<MetaSave>,
+    towers: Res<SelectableTowers>,
+    mut run_rng: ResMut<RunRng>,
+    mut draft: ResMut<RewardDraft>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    // A draft restored from a saved run is kept as it was.
+    if draft.0.is_empty() {
+        draft.0 = draw_rewards(&mut run_rng.rng, &run, &meta, &towers, DRAFT_SIZE);
+    }
+    if draft.0.is_empty() {
+        next_state.set(GameState::Map);
+    }
+}
+
+fn spawn_reward_ui(mut commands: Commands, asset_server: Res<AssetServer>, draft: Res<RewardDraft>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(40.0),
+                ..default()
+            },
+            background_color: Color::rgb(0.1, 0.12, 0.1).into(),
+            ..default()
+        },
+        RewardUi,
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            "Choose a reward",
+            TextStyle {
+                font: font.clone(),
+                font_size: 60.0,
+                color: Color::WHITE,
+            },
+        ));
+        parent.spawn(NodeBundle {
+            style: Style {
+                column_gap: Val::Px(40.0),
+                ..default()
+            },
+            ..default()
+        }).with_children(|parent| {
+            for (index, reward) in draft.0.iter().enumerate() {
+                parent.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(260.0),
+                            height: Val::Px(160.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: Color::rgb(0.3, 0.6, 0.3).into(),
+                        ..default()
+                    },
+                    RewardButton(index),
+                )).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        reward.label(),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                    ));
+                });
+            }
+        });
+    });
+}
+
+fn despawn_reward_ui(mut commands: Commands, reward_ui: Query<Entity, With<RewardUi>>) {
+    for entity in &reward_ui {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn queue_reward_pick(
+    mut actions: ResMut<ActionQueue>,
+    buttons: Query<(&Interaction, &RewardButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction == Interaction::Pressed {
+            actions.push(PlayerAction::PickReward(button.0));
+        }
+    }
+}
+
+fn pick_reward(
+    mut actions: EventReader<PlayerAction>,
+    mut run: ResMut<Run>,
+    mut towers: ResMut<SelectableTowers>,
+    mut magnet: ResMut<CoinMagnet>,
+    mut draft: ResMut<RewardDraft>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for action in actions.iter() {
+        let PlayerAction::PickReward(index) = *action else {
+            continue;
+        };
+        let Some(reward) = draft.0.get(index).copied() else {
+            continue;
+        };
+        match reward {
+            Reward::Tower(tower) => towers.possible_towers.push(towers::buyable_tower(tower)),
+            Reward::Upgrade(upgrade) => run.upgrades.push(upgrade),
+            Reward::Relic(relic) => {
+                if relic == Relic::CoinMagnet {
+                    magnet.0 = true;
+                }
+                run.relics.push(relic);
+            }
+        }
+        draft.0.clear();
+        next_state.set(GameState::Map);
+        return;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn empty_run() -> Run {
+        Run { nodes: Vec::new(), current: None, gold: 0, rest_money: 0, upgrades: Vec::new(), relics: Vec::new(), ascension: 0 }
+    }
+
+    fn loadout(owned: &[Tower]) -> SelectableTowers {
+        SelectableTowers {
+            possible_towers: owned.iter().map(|tower| towers::buyable_tower(*tower)).collect(),
+            selected_tower: None,
+        }
+    }
+
+    fn drafts(run: &Run, meta: &MetaSave, towers: &SelectableTowers) -> Vec<Vec<Reward>> {
+        (0..300)
+            .map(|seed| draw_rewards(&mut ChaCha8Rng::seed_from_u64(seed), run, meta, towers, DRAFT_SIZE))
+            .collect()
+    }
+
+    #[test]
+    fn drafts_have_no_duplicates() {
+        for draft in drafts(&empty_run(), &MetaSave::default(), &loadout(&[])) {
+            assert_eq!(draft.len(), DRAFT_SIZE);
+            for (i, reward) in draft.iter().enumerate() {
+                assert!(!draft[i + 1..].contains(reward), "{reward:?} drawn twice in {draft:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn locked_towers_are_never_drawn() {
+        let meta = MetaSave::default();
+        let drawn: Vec<Reward> = drafts(&empty_run(), &meta, &loadout(&[])).concat();
+        for tower in [Tower::SplashProjectile, Tower::PiercingProjectile] {
+            assert!(!meta.unlocked_towers.contains(&tower));
+            assert!(!drawn.contains(&Reward::Tower(tower)));
+        }
+        assert!(drawn.contains(&Reward::Tower(Tower::AntiAir)));
+    }
+
+    #[test]
+    fn owned_out_rewards_are_never_drawn() {
+        let mut run = empty_run();
+        run.upgrades = vec![Upgrade::ProjectileDamage; 5];
+        run.upgrades.extend([Upgrade::TowerHealth; 4]);
+        run.relics.push(Relic::CoinMagnet);
+        let drawn: Vec<Reward> = drafts(&run, &MetaSave::default(), &loadout(&[Tower::NormalProjectile])).concat();
+        assert!(!drawn.contains(&Reward::Upgrade(Upgrade::ProjectileDamage)));
+        assert!(!drawn.contains(&Reward::Relic(Relic::CoinMagnet)));
+        assert!(!drawn.contains(&Reward::Tower(Tower::NormalProjectile)));
+        // One copy short of the limit is still on offer.
+        assert!(drawn.contains(&Reward::Upgrade(Upgrade::TowerHealth)));
+    }
+
+    #[test]
+    fn draft_shrinks_when_little_is_left() {
+        let mut run = empty_run();
+        run.upgrades = [[Upgrade::ProjectileDamage; 5], [Upgrade::TowerHealth; 5]].concat();
+        run.upgrades.extend([Upgrade::MoneyPayout; 3]);
+        run.relics = vec![Relic::CoinMagnet, Relic::SharpenedPeas, Relic::PiggyDiscount, Relic::WarChest];
+        let meta = MetaSave::default();
+        let towers = loadout(&meta.unlocked_towers);
+        for draft in drafts(&run, &meta, &towers) {
+            assert_eq!(draft, vec![Reward::Relic(Relic::GoldenPig)]);
+        }
+    }
+}
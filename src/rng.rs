@@ -0,0 +1,21 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// The single source of randomness for a run, so a seed reproduces the run.
+/// Serializable so a saved run picks up exactly where its rolls left off.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct RunRng {
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+}
+
+impl RunRng {
+    pub fn from_seed(seed: u64) -> Self {
+        RunRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GameState,
+    actions::{ActionQueue, ActionSet, PlayerAction, ReplayMode, SimTick},
+    animation::DespawnOnFinish,
+    board::*,
+    enemies::{self, Enemies, EnemyHealth, EnemyProjectile, SpawnTimer, WAVE_1},
+    modifiers::{self, Stat},
+    mowers::Mower,
+    pickups::{Coin, CoinMagnet, SkyDrops},
+    relics::Relic,
+    resume::{self, PendingBattle, SavedScreen},
+    rewards::{RewardDraft, Upgrade},
+    rng::RunRng,
+    save::{self, MetaSave},
+    sim::GameplaySet,
+    towers::{self, Money, MoneyUpdated, Projectile, SelectableTowers},
+    ui::{BossHealthBar, FloatingText},
+};
+
+/// Rows of the map before the boss row.
+const MAP_ROWS: usize = 7;
+pub const STARTING_MONEY: u32 = 500;
+/// Gold a shop takes for a reward draft.
+const SHOP_PRICE: u32 = 100;
+/// Extra starting money for the battle after a rest.
+const REST_MONEY: u32 = 150;
+
+/// Odds of each node kind on the rows between the first battle and the rest before the boss.
+/// Events stay off the map until they have a screen.
+const NODE_WEIGHTS: [(NodeKind, u32); 4] = [
+    (NodeKind::Battle, 45),
+    (NodeKind::Elite, 12),
+    (NodeKind::Shop, 10),
+    (NodeKind::Rest, 15),
+];
+
+/// Enemies normal battles draw from. Later floors unlock more of the list.
+const BATTLE_POOL: [Enemies; 9] = [
+    Enemies::Basic,
+    Enemies::FastBasic,
+    Enemies::BasicHighHealth,
+    Enemies::Armored,
+    Enemies::Sidestepper,
+    Enemies::Dodger,
+    Enemies::PoleVaulter,
+    Enemies::Flyer,
+    Enemies::Digger,
+];
+
+const ELITE_POOL: [Enemies; 6] = [
+    Enemies::BasicHighHealth,
+    Enemies::HeavyArmored,
+    Enemies::Summoner,
+    Enemies::Gunner,
+    Enemies::Splitter,
+    Enemies::PoleVaulter,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum NodeKind {
+    Battle,
+    Elite,
+    Shop,
+    Event,
+    Rest,
+    Boss,
+}
+
+impl NodeKind {
+    pub fn is_battle(&self) -> bool {
+        matches!(self, NodeKind::Battle | NodeKind::Elite | NodeKind::Boss)
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            NodeKind::Battle => "Battle",
+            NodeKind::Elite => "Elite",
+            NodeKind::Shop => "Shop",
+            NodeKind::Event => "Event",
+            NodeKind::Rest => "Rest",
+            NodeKind::Boss => "Boss",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapNode {
+    pub kind: NodeKind,
+    pub row: usize,
+    pub column: usize,
+    /// Indices of the nodes on the next row this node leads to.
+    pub next: Vec<usize>,
+}
+
+/// State of the current run: the map and how far along it the player is.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct Run {
+    pub nodes: Vec<MapNode>,
+    pub current: Option<usize>,
+    pub gold: u32,
+    /// Added to the next battle's starting money, from resting.
+    #[serde(default)]
+    pub rest_money: u32,
+    pub upgrades: Vec<Upgrade>,
+    pub relics: Vec<Relic>,
+    pub ascension: u32,
+}
+
+impl Run {
+    pub fn generate(rng: &mut impl Rng) -> Self {
+        let mut rows: Vec<Vec<usize>> = Vec::new();
+        let mut nodes = Vec::new();
+        for row in 0..=MAP_ROWS {
+            let width = if row == MAP_ROWS { 1 } else { rng.gen_range(2..=4) };
+            let mut indices = Vec::new();
+            for column in 0..width {
+                let kind = match row {
+                    0 => NodeKind::Battle,
+                    _ if row == MAP_ROWS => NodeKind::Boss,
+                    _ if row == MAP_ROWS - 1 => NodeKind::Rest,
+                    _ => pick_node_kind(rng, row),
+                };
+                indices.push(nodes.len());
+                nodes.push(MapNode { kind, row, column, next: Vec::new() });
+            }
+            rows.push(indices);
+        }
+
+        // Each node leads to the node at the same relative spot on the next row, sometimes a neighbour too,
+        // and every node gets at least one way in.
+        for row in 0..MAP_ROWS {
+            let (from, to) = (&rows[row], &rows[row + 1]);
+            for (column, &node) in from.iter().enumerate() {
+                let target = relative_column(column, from.len(), to.len());
+                nodes[node].next.push(to[target]);
+                let neighbour = if rng.gen_bool(0.5) { target + 1 } else { target.wrapping_sub(1) };
+                if neighbour < to.len() && rng.gen_bool(0.4) {
+                    nodes[node].next.push(to[neighbour]);
+                }
+            }
+            for (column, &node) in to.iter().enumerate() {
+                if !from.iter().any(|&source| nodes[source].next.contains(&node)) {
+                    let source = from[relative_column(column, to.len(), from.len())];
+                    nodes[source].next.push(node);
+                }
+            }
+        }
+
+        Run {
+            nodes,
+            current: None,
+            gold: 0,
+            rest_money: 0,
+            upgrades: Vec::new(),
+            relics: Vec::new(),
+            ascension: 0,
+        }
+    }
+
+    /// Nodes the player can move to next.
+    pub fn available(&self) -> Vec<usize> {
+        match self.current {
+            Some(current) => self.nodes[current].next.clone(),
+            None => (0..self.nodes.len()).filter(|&i| self.nodes[i].row == 0).collect(),
+        }
+    }
+
+    pub fn current_kind(&self) -> Option<NodeKind> {
+        self.current.map(|current| self.nodes[current].kind)
+    }
+}
+
+fn pick_node_kind(rng: &mut impl Rng, row: usize) -> NodeKind {
+    // No elites before the player has had a couple of battles.
+    let weights: Vec<(NodeKind, u32)> = NODE_WEIGHTS.iter()
+        .copied()
+        .filter(|(kind, _)| row > 1 || *kind != NodeKind::Elite)
+        .collect();
+    let total: u32 = weights.iter().map(|(_, weight)| weight).sum();
+    let mut roll = rng.gen_range(0..total);
+    for (kind, weight) in weights {
+        if roll < weight {
+            return kind;
+        }
+        roll -= weight;
+    }
+    NodeKind::Battle
+}
+
+fn relative_column(column: usize, width: usize, other_width: usize) -> usize {
+    if width <= 1 {
+        return other_width / 2;
+    }
+    (column as f32 / (width - 1) as f32 * (other_width - 1) as f32).round() as usize
+}
+
+/// What the battle behind the chosen node looks like.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct BattleConfig {
+    pub lanes: (i32, i32),
+    pub night: bool,
+    pub wave: Vec<(Enemies, f32)>,
+    pub starting_money: u32,
+    /// Gold added to the run once the wave is cleared.
+    pub reward: u32,
+}
+
+impl BattleConfig {
+    pub fn for_node(kind: NodeKind, floor: usize, rng: &mut impl Rng) -> Self {
+        let lanes = if floor < 2 { (-3, 1) } else { (-4, 2) };
+        let (wave, reward) = match kind {
+            NodeKind::Boss => (WAVE_1.to_vec(), 0),
+            NodeKind::Elite => (generate_wave(rng, &ELITE_POOL, 8 + floor * 3, floor), 100),
+            _ => {
+                let unlocked = (3 + floor).min(BATTLE_POOL.len());
+                (generate_wave(rng, &BATTLE_POOL[..unlocked], 6 + floor * 3, floor), 50)
+            }
+        };
+        BattleConfig {
+            lanes,
+            night: kind != NodeKind::Boss && rng.gen_bool(0.25),
+            wave,
+            starting_money: STARTING_MONEY,
+            reward,
+        }
+    }
+}
+
+fn generate_wave(rng: &mut impl Rng, pool: &[Enemies], count: usize, floor: usize) -> Vec<(Enemies, f32)> {
+    let max_delay = (12.0 - floor as f32).max(4.0);
+    (0..count)
+        .map(|i| {
+            let delay = if i == 0 { 20.0 } else { rng.gen_range(1.0..max_delay) };
+            (pool[rng.gen_range(0..pool.len())], delay)
+        })
+        .collect()
+}
+
+#[derive(Component)]
+pub struct MapUi;
+
+#[derive(Component)]
+pub struct MapNodeButton(pub usize);
+
+pub struct RunPlugin;
+
+impl Plugin for RunPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, start_run)
+            .add_systems(OnEnter(GameState::Map), spawn_map_ui)
+            .add_systems(OnExit(GameState::Map), despawn_map_ui)
+            .add_systems(Update, queue_node_choice.run_if(in_state(GameState::Map)))
+            .add_systems(FixedUpdate, choose_node.in_set(ActionSet::Handle).run_if(in_state(GameState::Map)))
+            .add_systems(OnEnter(GameState::Battle), start_battle)
+            .add_systems(FixedUpdate, check_battle_won
+                .after(enemies::despawn_dead_enemies)
+                .in_set(GameplaySet::Resolve)
+                .run_if(in_state(GameState::Battle)))
+            .add_systems(OnExit(GameState::Battle), cleanup_battle);
+    }
+}
+
+fn start_run(
+    mut commands: Commands,
+    mut run_rng: ResMut<RunRng>,
+    mut meta: ResMut<MetaSave>,
+    mut towers: ResMut<SelectableTowers>,
+    mut magnet: ResMut<CoinMagnet>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut replay_mode: ResMut<ReplayMode>,
+) {
+    let saved = if replay_mode.is_playing() { None } else { resume::load_run() };
+    if let Some(save) = saved {
+        // A resumed run can't be replayed from its seed.
+        *replay_mode = ReplayMode::Off;
+        *run_rng = save.rng;
+        commands.insert_resource(SimTick(save.tick));
+        towers.possible_towers = save.towers.iter().map(|tower| towers::buyable_tower(*tower)).collect();
+        magnet.0 = save.coin_magnet;
+        commands.insert_resource(save.run);
+        match save.screen {
+            SavedScreen::Map => {}
+            SavedScreen::Reward(draft) => {
+                commands.insert_resource(RewardDraft(draft));
+                next_state.set(GameState::Reward);
+            }
+            SavedScreen::Battle(battle) => {
+                commands.insert_resource(battle.config.clone());
+                commands.insert_resource(PendingBattle(battle));
+                next_state.set(GameState::Battle);
+            }
+        }
+        return;
+    }
+    let mut run = Run::generate(&mut run_rng.rng);
+    run.ascension = meta.next_ascension();
+    commands.insert_resource(run);
+    towers.possible_towers = meta.starting_towers.iter().map(|tower| towers::buyable_tower(*tower)).collect();
+    if let ReplayMode::Recording(replay) = &mut *replay_mode {
+        replay.meta = meta.clone();
+    }
+    if !replay_mode.is_playing() {
+        meta.statistics.runs_started += 1;
+        save::write_meta(&meta);
+    }
+}
+
+fn spawn_map_ui(mut commands: Commands, asset_server: Res<AssetServer>, run: Res<Run>) {
+    build_map_ui(&mut commands, &asset_server, &run);
+}
+
+fn despawn_map_ui(mut commands: Commands, map_ui: Query<Entity, With<MapUi>>) {
+    for entity in &map_ui {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn build_map_ui(commands: &mut Commands, asset_server: &Res<AssetServer>, run: &Run) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let available = run.available();
+    let rows = run.nodes.iter().map(|node| node.row).max().unwrap_or(0);
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::SpaceEvenly,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgb(0.1, 0.12, 0.1).into(),
+            ..default()
+        },
+        MapUi,
+    )).with_children(|parent| {
+        for row in 0..=rows {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Percent(60.0),
+                    justify_content: JustifyContent::SpaceEvenly,
+                    ..default()
+                },
+                ..default()
+            }).with_children(|parent| {
+                for (index, node) in run.nodes.iter().enumerate().filter(|(_, node)| node.row == row) {
+                    let color = if available.contains(&index) {
+                        Color::rgb(0.3, 0.6, 0.3)
+                    } else if run.current == Some(index) {
+                        Color::GOLD
+                    } else {
+                        Color::rgb(0.25, 0.25, 0.25)
+                    };
+                    parent.spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(140.0),
+                                height: Val::Px(60.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: color.into(),
+                            ..default()
+                        },
+                        MapNodeButton(index),
+                    )).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            node.kind.label(),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 30.0,
+                                color: Color::WHITE,
+                            },
+                        ));
+                    });
+                }
+            });
+        }
+        let rest = if run.rest_money > 0 { format!("   Next battle: +{} money", run.rest_money) } else { String::new() };
+        parent.spawn(TextBundle::from_section(
+            format!("Gold: {} (shops cost {SHOP_PRICE}){rest}", run.gold),
+            TextStyle {
+                font: font.clone(),
+                font_size: 40.0,
+                color: Color::GOLD,
+            },
+        ));
+    });
+}
+
+fn queue_node_choice(
+    mut actions: ResMut<ActionQueue>,
+    buttons: Query<(&Interaction, &MapNodeButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction == Interaction::Pressed {
+            actions.push(PlayerAction::ChooseNode(button.0));
+        }
+    }
+}
+
+fn choose_node(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    asset_server: Res<AssetServer>,
+    mut run: ResMut<Run>,
+    mut run_rng: ResMut<RunRng>,
+    mut next_state: ResMut<NextState<GameState>>,
+    map_ui: Query<Entity, With<MapUi>>,
+) {
+    for action in actions.iter() {
+        let PlayerAction::ChooseNode(index) = *action else {
+            continue;
+        };
+        if !run.available().contains(&index) {
+            continue;
+        }
+        run.current = Some(index);
+        let (kind, row) = (run.nodes[index].kind, run.nodes[index].row);
+        match kind {
+            _ if kind.is_battle() => {
+                let mut battle = BattleConfig::for_node(kind, row, &mut run_rng.rng);
+                battle.starting_money += std::mem::take(&mut run.rest_money);
+                commands.insert_resource(battle);
+                next_state.set(GameState::Battle);
+                return;
+            }
+            NodeKind::Shop if run.gold >= SHOP_PRICE => {
+                run.gold -= SHOP_PRICE;
+                next_state.set(GameState::Reward);
+                return;
+            }
+            NodeKind::Rest => run.rest_money += REST_MONEY,
+            // A shop the player can't afford, or an event on a map saved before they were taken out.
+            _ => {}
+        }
+        for entity in &map_ui {
+            commands.entity(entity).despawn_recursive();
+        }
+        build_map_ui(&mut commands, &asset_server, &run);
+        return;
+    }
+}
+
+pub fn start_battle(
+    run: Res<Run>,
+    battle: Res<BattleConfig>,
+    mut board: ResMut<Board>,
+    mut money: ResMut<Money>,
+    mut money_updated: EventWriter<MoneyUpdated>,
+    mut sky_drops: ResMut<SkyDrops>,
+) {
+    *board = generate_board(battle.lanes);
+    money.0 = modifiers::modify_u32(&run, Stat::StartingMoney, battle.starting_money);
+    money_updated.send(MoneyUpdated {
+        new_value: money.0
+    });
+    *sky_drops = if battle.night { SkyDrops::night() } else { SkyDrops::day() };
+}
+
+/// Every enemy in the wave has spawned and none are left.
+pub fn wave_cleared(battle: &BattleConfig, spawn_timers: &Query<&SpawnTimer>, enemies: &Query<(), With<EnemyHealth>>) -> bool {
+    let wave_done = !spawn_timers.is_empty() && spawn_timers.iter().all(|timer| timer.index >= battle.wave.len());
+    wave_done && enemies.is_empty()
+}
+
+fn check_battle_won(
+    mut run: ResMut<Run>,
+    battle: Res<BattleConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
+    spawn_timers: Query<&SpawnTimer>,
+    enemies: Query<(), With<EnemyHealth>>,
+) {
+    if !wave_cleared(&battle, &spawn_timers, &enemies) {
+        return;
+    }
+    run.gold += battle.reward;
+    next_state.set(if run.current_kind() == Some(NodeKind::Boss) { GameState::Victory } else { GameState::Reward });
+}
+
+/// Everything a battle spawned goes away with it; the map starts from a clean slate.
+fn cleanup_battle(
+    mut commands: Commands,
+    entities: Query<Entity, Or<(
+        With<EnemyHealth>, With<EnemyProjectile>, With<towers::Health>, With<Projectile>, With<Coin>,
+        With<Mower>, With<FloatingText>, With<SpawnTimer>, With<DespawnOnFinish>, With<BossHealthBar>,
+    )>>,
+) {
+    for entity in &entities {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn maps() -> impl Iterator<Item = Run> {
+        (0..200).map(|seed| Run::generate(&mut ChaCha8Rng::seed_from_u64(seed)))
+    }
+
+    #[test]
+    fn map_starts_with_battles_and_ends_at_the_boss() {
+        for run in maps() {
+            let row = |row: usize| run.nodes.iter().filter(move |node| node.row == row);
+            assert!(row(0).count() >= 2);
+            assert!(row(0).all(|node| node.kind == NodeKind::Battle));
+            assert!(row(MAP_ROWS - 1).all(|node| node.kind == NodeKind::Rest));
+            let boss: Vec<&MapNode> = row(MAP_ROWS).collect();
+            assert_eq!(boss.len(), 1);
+            assert_eq!(boss[0].kind, NodeKind::Boss);
+            assert!(boss[0].next.is_empty());
+            assert!(run.nodes.iter().all(|node| node.kind != NodeKind::Boss || node.row == MAP_ROWS));
+            assert!(row(1).all(|node| node.kind != NodeKind::Elite));
+            assert!(run.nodes.iter().all(|node| node.kind != NodeKind::Event));
+        }
+    }
+
+    #[test]
+    fn every_node_is_reachable_and_leads_on() {
+        for run in maps() {
+            let mut reached = vec![false; run.nodes.len()];
+            let mut open = run.available();
+            while let Some(node) = open.pop() {
+                if !reached[node] {
+                    reached[node] = true;
+                    open.extend(&run.nodes[node].next);
+                }
+            }
+            assert!(reached.iter().all(|reached| *reached));
+            for node in &run.nodes {
+                for &next in &node.next {
+                    assert_eq!(run.nodes[next].row, node.row + 1);
+                }
+                assert!(node.row == MAP_ROWS || !node.next.is_empty());
+            }
+        }
+    }
+}
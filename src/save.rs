@@ -0,0 +1,253 @@
+use std::{env, fs, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    GameState,
+    actions::not_replaying,
+    enemies::EnemyDied,
+    run::Run,
+    towers::Tower,
+};
+
+/// Overrides where saves are kept, e.g. to point tests at a temp dir.
+pub const DATA_DIR_ENV: &str = "PVZ_ROGUE_DATA_DIR";
+const META_FILE: &str = "meta.json";
+const META_VERSION: u32 = 1;
+
+/// `META_MIGRATIONS[i]` upgrades a save from version `i + 1` to `i + 2`. Append one whenever
+/// the format changes and bump `META_VERSION`, so older saves keep loading.
+const META_MIGRATIONS: &[fn(&mut Value)] = &[];
+const _: () = assert!(META_VERSION as usize == META_MIGRATIONS.len() + 1);
+
+/// Towers every new profile can use. The starting loadout is taken from these.
+const DEFAULT_UNLOCKED_TOWERS: [Tower; 4] = [Tower::Money, Tower::NormalProjectile, Tower::AntiAir, Tower::BackwardsProjectile];
+const DEFAULT_STARTING_TOWERS: [Tower; 2] = [Tower::Money, Tower::NormalProjectile];
+/// Unlocked one at a time, in this order, by winning runs.
+const TOWER_UNLOCK_ORDER: [Tower; 2] = [Tower::SplashProjectile, Tower::PiercingProjectile];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunRecord {
+    pub gold: u32,
+    pub floor: usize,
+    pub won: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Statistics {
+    pub runs_started: u32,
+    pub runs_won: u32,
+    pub enemies_killed: u32,
+}
+
+/// Progress kept between launches.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MetaSave {
+    pub version: u32,
+    /// Towers that can show up in reward drafts.
+    pub unlocked_towers: Vec<Tower>,
+    /// Towers every run starts with.
+    pub starting_towers: Vec<Tower>,
+    /// Gold banked over all runs.
+    pub total_gold: u32,
+    pub runs: Vec<RunRecord>,
+    pub completed_ascensions: Vec<u32>,
+    pub statistics: Statistics,
+}
+
+impl Default for MetaSave {
+    fn default() -> Self {
+        MetaSave {
+            version: META_VERSION,
+            unlocked_towers: DEFAULT_UNLOCKED_TOWERS.to_vec(),
+            starting_towers: DEFAULT_STARTING_TOWERS.to_vec(),
+            total_gold: 0,
+            runs: Vec::new(),
+            completed_ascensions: Vec::new(),
+            statistics: Statistics::default(),
+        }
+    }
+}
+
+impl MetaSave {
+    /// The ascension a new run is played at: one above the highest completed.
+    pub fn next_ascension(&self) -> u32 {
+        self.completed_ascensions.iter().max().map_or(0, |highest| highest + 1)
+    }
+}
+
+pub fn data_dir() -> PathBuf {
+    env::var_os(DATA_DIR_ENV)
+        .map(PathBuf::from)
+        .or_else(|| dirs::data_dir().map(|dir| dir.join("pvz_rogue")))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Brings a raw save up to the version after the last of `migrations`.
+fn migrate(mut save: Value, migrations: &[fn(&mut Value)]) -> Value {
+    // Every save ever written has a version; one without is read as the first.
+    let mut version = save.get("version").and_then(Value::as_u64).unwrap_or(1).max(1) as usize;
+    while version <= migrations.len() {
+        migrations[version - 1](&mut save);
+        version += 1;
+    }
+    if let Some(object) = save.as_object_mut() {
+        object.insert("version".to_string(), Value::from(version as u64));
+    }
+    save
+}
+
+/// Loads the meta save, falling back to a fresh profile when there is none or it can't be read.
+pub fn load_meta() -> MetaSave {
+    let path = data_dir().join(META_FILE);
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return MetaSave::default();
+    };
+    let save = serde_json::from_str::<Value>(&contents)
+        .map(|save| migrate(save, META_MIGRATIONS))
+        .and_then(serde_json::from_value::<MetaSave>);
+    match save {
+        Ok(save) => {
+            if save.version > META_VERSION {
+                warn!("{} was written by a newer version ({}), fields may be lost", path.display(), save.version);
+            }
+            save
+        }
+        Err(err) => {
+            warn!("could not read {}: {err}", path.display());
+            MetaSave::default()
+        }
+    }
+}
+
+pub fn write_meta(save: &MetaSave) {
+    write_json(META_FILE, save);
+}
+
+/// Writes `value` to `file` in the data dir.
+pub fn write_json(file: &str, value: &impl Serialize) {
+    let dir = data_dir();
+    let path = dir.join(file);
+    // Written to a temp file first so a crash mid-write never leaves a truncated save.
+    let tmp = path.with_extension("json.tmp");
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&tmp, serde_json::to_string_pretty(value).unwrap()))
+        .and_then(|_| fs::rename(&tmp, &path));
+    if let Err(err) = result {
+        warn!("could not write {}: {err}", path.display());
+    }
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(load_meta())
+            // Not gated on the battle, so kills on the tick that ends it still count.
+            .add_systems(Update, count_kills)
+            // A replay plays against a copy of someone's progress, which must not be written back.
+            .add_systems(OnEnter(GameState::GameOver), record_run_end.run_if(not_replaying))
+            .add_systems(OnEnter(GameState::Victory), record_run_end.run_if(not_replaying));
+    }
+}
+
+fn count_kills(mut meta: ResMut<MetaSave>, mut enemy_died: EventReader<EnemyDied>) {
+    meta.statistics.enemies_killed += enemy_died.iter().count() as u32;
+}
+
+fn record_run_end(mut meta: ResMut<MetaSave>, run: Res<Run>, state: Res<State<GameState>>) {
+    let won = *state.get() == GameState::Victory;
+    let floor = run.current.map_or(0, |current| run.nodes[current].row);
+    meta.runs.push(RunRecord { gold: run.gold, floor, won });
+    meta.total_gold += run.gold;
+    if won {
+        meta.statistics.runs_won += 1;
+        meta.completed_ascensions.push(run.ascension);
+        let locked = TOWER_UNLOCK_ORDER.iter().find(|tower| !meta.unlocked_towers.contains(tower)).copied();
+        if let Some(tower) = locked {
+            meta.unlocked_towers.push(tower);
+        }
+    }
+    write_meta(&meta);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::with_data_dir;
+
+    fn as_json(save: &MetaSave) -> Value {
+        serde_json::to_value(save).unwrap()
+    }
+
+    #[test]
+    fn missing_save_starts_a_fresh_profile() {
+        with_data_dir("missing", |_| {
+            assert_eq!(as_json(&load_meta()), as_json(&MetaSave::default()));
+        });
+    }
+
+    #[test]
+    fn corrupt_save_starts_a_fresh_profile() {
+        with_data_dir("corrupt", |dir| {
+            fs::write(dir.join(META_FILE), "{\"version\": 1, \"total_gold\": ").unwrap();
+            assert_eq!(as_json(&load_meta()), as_json(&MetaSave::default()));
+        });
+    }
+
+    #[test]
+    fn written_save_loads_back() {
+        with_data_dir("round_trip", |_| {
+            let mut save = MetaSave::default();
+            save.total_gold = 120;
+            save.unlocked_towers.push(Tower::SplashProjectile);
+            save.runs.push(RunRecord { gold: 120, floor: 4, won: true });
+            save.completed_ascensions.push(0);
+            save.statistics.enemies_killed = 37;
+            write_meta(&save);
+            assert_eq!(as_json(&load_meta()), as_json(&save));
+        });
+    }
+
+    #[test]
+    fn missing_fields_fall_back_to_defaults() {
+        with_data_dir("partial", |dir| {
+            fs::write(dir.join(META_FILE), r#"{"version": 1, "total_gold": 55}"#).unwrap();
+            let save = load_meta();
+            assert_eq!(save.total_gold, 55);
+            assert_eq!(save.unlocked_towers, DEFAULT_UNLOCKED_TOWERS.to_vec());
+        });
+    }
+
+    #[test]
+    fn migrations_run_in_order_from_the_saved_version() {
+        fn rename_gold(save: &mut Value) {
+            let gold = save["gold"].take();
+            save["total_gold"] = gold;
+        }
+        fn add_statistics(save: &mut Value) {
+            save["statistics"] = json!({ "runs_started": 3, "runs_won": 0, "enemies_killed": 0 });
+        }
+        let migrations: &[fn(&mut Value)] = &[rename_gold, add_statistics];
+
+        let migrated = migrate(json!({ "version": 1, "gold": 10 }), migrations);
+        assert_eq!(migrated["version"], 3);
+        assert_eq!(migrated["total_gold"], 10);
+        assert_eq!(migrated["statistics"]["runs_started"], 3);
+
+        let migrated = migrate(json!({ "version": 2, "total_gold": 10 }), migrations);
+        assert_eq!(migrated["version"], 3);
+        assert_eq!(migrated["total_gold"], 10);
+        assert!(migrated.get("gold").is_none());
+
+        let save: MetaSave = serde_json::from_value(migrate(json!({ "version": 1, "gold": 10 }), migrations)).unwrap();
+        assert_eq!(save.total_gold, 10);
+        assert_eq!(save.statistics.runs_started, 3);
+    }
+}
@@ -0,0 +1,109 @@
+use std::env;
+
+use bevy::{ecs::schedule::ExecutorKind, prelude::*, transform::TransformSystem};
+
+/// Simulation ticks per second unless `--tick-rate` says otherwise.
+pub const DEFAULT_TICK_RATE: f64 = 60.0;
+
+/// Reads `--tick-rate <hz>` from the command line.
+pub fn tick_rate_from_args() -> Option<f64> {
+    let args: Vec<String> = env::args().collect();
+    let rate = args.iter().position(|arg| arg == "--tick-rate").and_then(|i| args.get(i + 1))?;
+    match rate.parse::<f64>() {
+        Ok(rate) if rate > 0.0 => Some(rate),
+        _ => {
+            error!("invalid tick rate {rate}, using {DEFAULT_TICK_RATE}");
+            None
+        }
+    }
+}
+
+/// Order of a simulation tick in `FixedUpdate`.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SimSet {
+    Begin,
+    /// Everything that moves, fights or spends money.
+    Gameplay,
+    End,
+}
+
+/// Steps of `SimSet::Gameplay`, after player actions are handled. Together with the
+/// single-threaded `FixedUpdate` this runs every tick in the same order, so rolls of the
+/// run's rng, and with them whole battles, come out the same for the same seed and actions.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum GameplaySet {
+    /// Timers that spawn, shoot, pay out or change what an entity is doing.
+    Act,
+    Move,
+    /// Collisions and damage.
+    Combat,
+    /// Deaths and what they leave behind, then whether the battle is over.
+    Resolve,
+}
+
+/// Draws an entity moved by the simulation between where it was on the last two ticks,
+/// so motion stays smooth when frames and ticks don't line up.
+#[derive(Component, Clone, Copy)]
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+}
+
+impl Interpolated {
+    pub fn at(position: Vec3) -> Self {
+        Interpolated { previous: position, current: position }
+    }
+}
+
+pub struct SimPlugin {
+    pub tick_rate: f64,
+}
+
+impl Plugin for SimPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(FixedTime::new_from_secs((1.0 / self.tick_rate) as f32))
+            .edit_schedule(FixedUpdate, |schedule| {
+                schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+            })
+            .configure_sets(FixedUpdate, (SimSet::Begin, SimSet::Gameplay, SimSet::End).chain())
+            .configure_sets(FixedUpdate, (GameplaySet::Act, GameplaySet::Move, GameplaySet::Combat, GameplaySet::Resolve)
+                .chain()
+                .in_set(SimSet::Gameplay))
+            .add_systems(First, snap_to_sim)
+            .add_systems(FixedUpdate, begin_tick.in_set(SimSet::Begin))
+            .add_systems(FixedUpdate, end_tick.in_set(SimSet::End))
+            .add_systems(PostUpdate, interpolate.before(TransformSystem::TransformPropagate));
+    }
+}
+
+/// Puts interpolated entities back where the simulation left them, so nothing outside
+/// rendering ever reads an in-between position.
+pub fn snap_to_sim(mut query: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in &mut query {
+        transform.translation = interpolated.current;
+    }
+}
+
+fn begin_tick(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in &mut query {
+        interpolated.previous = transform.translation;
+    }
+}
+
+fn end_tick(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in &mut query {
+        interpolated.current = transform.translation;
+    }
+}
+
+fn interpolate(fixed_time: Res<FixedTime>, mut query: Query<(&mut Transform, &mut Interpolated)>) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).min(1.0);
+    for (mut transform, mut interpolated) in &mut query {
+        // Moved outside a tick, e.g. when a saved battle is restored: jump there instead of sliding.
+        if transform.translation != interpolated.current {
+            *interpolated = Interpolated::at(transform.translation);
+        }
+        transform.translation = interpolated.previous.lerp(interpolated.current, alpha);
+    }
+}
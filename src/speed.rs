@@ -0,0 +1,169 @@
+use bevy::{prelude::*, ui::UiSystem};
+
+use crate::{GameState, pickups};
+
+/// Fast-forward options, with their hotkeys.
+const SPEEDS: [(f32, KeyCode); 3] = [(1.0, KeyCode::F1), (2.0, KeyCode::F2), (4.0, KeyCode::F3)];
+const PAUSE_KEY: KeyCode = KeyCode::Space;
+
+/// How fast the battle runs. Scales `Time`, which drives every fixed tick, so all gameplay
+/// timers and movement speed up or stop together.
+#[derive(Resource, Clone, Copy, PartialEq)]
+pub struct GameSpeed {
+    pub paused: bool,
+    pub scale: f32,
+}
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        GameSpeed { paused: false, scale: 1.0 }
+    }
+}
+
+#[derive(Component)]
+struct SpeedControls;
+
+#[derive(Component, Clone, Copy)]
+enum SpeedButton {
+    Pause,
+    Scale(f32),
+}
+
+impl SpeedButton {
+    fn label(&self) -> String {
+        match self {
+            SpeedButton::Pause => "Pause [Space]".to_string(),
+            SpeedButton::Scale(scale) => {
+                let key = SPEEDS.iter().position(|(speed, _)| speed == scale).unwrap() + 1;
+                format!("{scale}x [F{key}]")
+            }
+        }
+    }
+
+    fn apply(&self, speed: &mut GameSpeed) {
+        match *self {
+            SpeedButton::Pause => speed.paused = !speed.paused,
+            SpeedButton::Scale(scale) => *speed = GameSpeed { paused: false, scale },
+        }
+    }
+
+    fn color(&self, speed: &GameSpeed) -> BackgroundColor {
+        let active = match *self {
+            SpeedButton::Pause => speed.paused,
+            SpeedButton::Scale(scale) => !speed.paused && speed.scale == scale,
+        };
+        if active {
+            Color::rgb(0.3, 0.6, 0.3).into()
+        } else {
+            Color::rgba(0.0, 0.0, 0.0, 0.6).into()
+        }
+    }
+}
+
+pub struct SpeedPlugin;
+
+impl Plugin for SpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<GameSpeed>()
+            .add_systems(OnEnter(GameState::Battle), spawn_speed_controls)
+            // Map and reward picks are handled on fixed ticks too, so those screens always run at 1x.
+            .add_systems(OnExit(GameState::Battle), (despawn_speed_controls, reset_speed))
+            // Takes the click before anything on the board can see it.
+            .add_systems(PreUpdate, click_speed_buttons
+                .after(UiSystem::Focus)
+                .before(pickups::click_coins)
+                .run_if(in_state(GameState::Battle)))
+            .add_systems(Update, speed_hotkeys.run_if(in_state(GameState::Battle)))
+            .add_systems(Update, (apply_speed, highlight_speed_buttons).run_if(resource_changed::<GameSpeed>()));
+    }
+}
+
+fn spawn_speed_controls(mut commands: Commands, asset_server: Res<AssetServer>, speed: Res<GameSpeed>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let buttons = std::iter::once(SpeedButton::Pause).chain(SPEEDS.iter().map(|(scale, _)| SpeedButton::Scale(*scale)));
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                column_gap: Val::Px(8.0),
+                ..default()
+            },
+            z_index: ZIndex::Global(5),
+            ..default()
+        },
+        SpeedControls,
+    )).with_children(|parent| {
+        for button in buttons {
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(8.0)),
+                        ..default()
+                    },
+                    background_color: button.color(&speed),
+                    ..default()
+                },
+                button,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    button.label(),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                ));
+            });
+        }
+    });
+}
+
+fn despawn_speed_controls(mut commands: Commands, controls: Query<Entity, With<SpeedControls>>) {
+    for entity in &controls {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn reset_speed(mut speed: ResMut<GameSpeed>) {
+    *speed = GameSpeed::default();
+}
+
+fn click_speed_buttons(
+    mut input: ResMut<Input<MouseButton>>,
+    mut speed: ResMut<GameSpeed>,
+    buttons: Query<(&Interaction, &SpeedButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction == Interaction::Pressed {
+            button.apply(&mut speed);
+            input.clear_just_pressed(MouseButton::Left);
+        }
+    }
+}
+
+fn speed_hotkeys(input: Res<Input<KeyCode>>, mut speed: ResMut<GameSpeed>) {
+    if input.just_pressed(PAUSE_KEY) {
+        SpeedButton::Pause.apply(&mut speed);
+    }
+    if let Some((scale, _)) = SPEEDS.iter().find(|(_, key)| input.just_pressed(*key)) {
+        SpeedButton::Scale(*scale).apply(&mut speed);
+    }
+}
+
+fn apply_speed(speed: Res<GameSpeed>, mut time: ResMut<Time>) {
+    time.set_relative_speed(speed.scale);
+    if speed.paused {
+        time.pause();
+    } else {
+        time.unpause();
+    }
+}
+
+fn highlight_speed_buttons(speed: Res<GameSpeed>, mut buttons: Query<(&SpeedButton, &mut BackgroundColor)>) {
+    for (button, mut color) in &mut buttons {
+        *color = button.color(&speed);
+    }
+}
@@ -0,0 +1,81 @@
+//! Helpers shared by tests that need files or a running battle.
+
+use std::{env, fs, path::PathBuf, sync::Mutex};
+
+use bevy::{asset::AssetPlugin, prelude::*, time::TimeUpdateStrategy};
+
+use crate::{
+    GameState,
+    actions::{self, ReplayMode},
+    animation,
+    board::generate_board,
+    enemies::{self, Enemies},
+    mowers,
+    pickups::{self, CoinMagnet, SkyDrops},
+    rng::RunRng,
+    run::{BattleConfig, Run},
+    save::DATA_DIR_ENV,
+    sim::{self, SimSet},
+    towers::{self, Money, SelectableTowers, Tower},
+    ui,
+};
+
+pub const SEED: u64 = 7;
+
+/// `DATA_DIR_ENV` is process-wide, so tests touching files take turns.
+static DATA_DIR_LOCK: Mutex<()> = Mutex::new(());
+
+/// Points the data dir at an empty temp dir for the rest of the test.
+pub fn with_data_dir(name: &str, test: impl FnOnce(PathBuf)) {
+    let _lock = DATA_DIR_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let dir = env::temp_dir().join(format!("pvz_rogue_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    env::set_var(DATA_DIR_ENV, &dir);
+    test(dir.clone());
+    env::remove_var(DATA_DIR_ENV);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// A headless battle with lane switchers in the wave, so towers hitting them roll the rng.
+/// Each `update` runs exactly one tick.
+pub fn battle_app(mode: ReplayMode) -> App {
+    let mut app = App::new();
+    app
+        .add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .add_asset::<TextureAtlas>()
+        .add_state::<GameState>()
+        .add_plugins((
+            sim::SimPlugin { tick_rate: sim::DEFAULT_TICK_RATE }, actions::ActionPlugin, animation::AnimationPlugin,
+            towers::TowerPlugin, enemies::EnemiesPlugin, pickups::PickupPlugin, mowers::MowerPlugin,
+        ))
+        .insert_resource(mode)
+        .insert_resource(RunRng::from_seed(SEED))
+        .insert_resource(Run { nodes: Vec::new(), current: None, gold: 0, rest_money: 0, upgrades: Vec::new(), relics: Vec::new(), ascension: 0 })
+        .insert_resource(BattleConfig {
+            lanes: (-2, 2),
+            night: false,
+            wave: [Enemies::Dodger, Enemies::Sidestepper, Enemies::Basic, Enemies::Dodger, Enemies::Digger]
+                .into_iter()
+                .cycle()
+                .take(20)
+                .map(|enemy| (enemy, 1.0))
+                .collect(),
+            starting_money: 1000,
+            reward: 0,
+        })
+        .insert_resource(generate_board((-2, 2)))
+        .insert_resource(Money(1000))
+        .insert_resource(SkyDrops::day())
+        .insert_resource(CoinMagnet(true))
+        .add_systems(FixedUpdate, ui::attach_health_bars.in_set(SimSet::End))
+        .add_systems(Startup, |mut selectable: ResMut<SelectableTowers>, mut next_state: ResMut<NextState<GameState>>| {
+            selectable.possible_towers.push(towers::buyable_tower(Tower::NormalProjectile));
+            next_state.set(GameState::Battle);
+        });
+    let period = app.world.resource::<FixedTime>().period;
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(period));
+    app.finish();
+    app.cleanup();
+    app
+}
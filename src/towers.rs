@@ -1,7 +1,10 @@
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use bevy::{prelude::*, window::PrimaryWindow, utils::HashMap, };
 
-use crate::{MainCamera, board::*, enemies::EnemyHealth};
+use crate::{GameState, MainCamera, actions::{ActionQueue, ActionSet, PlayerAction}, modifiers::{self, Stat}, pickups, run::Run, sim::{GameplaySet, Interpolated}, speed::GameSpeed, animation::{self, AnimationClip, Clip, SpriteAnimation, Tilesheet}, board::*, enemies::{apply_damage, Armor, EnemyHealth, Flying, Untargetable}};
+
+const SPLASH_RADIUS: f32 = 60.0;
 
 
 #[derive(Resource)]
@@ -21,6 +24,19 @@ pub struct MoneyUpdated {
 #[derive(Component, Clone, Copy, Debug)]
 pub struct Health {
     pub health: i32,
+    pub max: i32,
+}
+
+/// Sent whenever a projectile deals damage, for feedback like damage numbers.
+#[derive(Event)]
+pub struct ProjectileHit {
+    pub target: Entity,
+    pub damage: i32,
+    /// Health actually taken off the target: not what armor absorbed or what overshot zero.
+    pub dealt: i32,
+    pub position: Vec3,
+    /// The tower that fired it, if any.
+    pub source: Option<TowerId>,
 }
 
 #[derive(Component, Clone, Debug)]
@@ -29,10 +45,30 @@ pub struct MoneyGain {
     pub gaintimer: Timer,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DamageType {
+    Normal,
+    Splash,
+    Piercing,
+}
+
+impl DamageType {
+    pub fn bypasses_armor(&self) -> bool {
+        match self {
+            DamageType::Normal => false,
+            DamageType::Splash | DamageType::Piercing => true,
+        }
+    }
+}
+
 #[derive(Component, Clone, Debug)]
 pub struct ProjectileTower {
     pub speed: f32,
     pub damage: i32,
+    pub damage_type: DamageType,
+    pub anti_air: bool,
+    /// 1.0 shoots towards the right of the board, -1.0 shoots backwards.
+    pub direction: f32,
     pub shoot_timer: Timer,
 }
 
@@ -40,6 +76,9 @@ pub struct ProjectileTower {
 pub struct Projectile {
     pub speed: f32,
     pub damage: i32,
+    pub damage_type: DamageType,
+    pub anti_air: bool,
+    pub direction: f32,
 }
 
 #[derive(Bundle, Clone, Debug)]
@@ -60,10 +99,23 @@ pub enum TowerBundle {
     SimpleProjectile(SimpleProjectileBundle)
 }
 
-#[derive(Hash,PartialEq, Eq, Debug, Clone, Copy)]
+impl TowerBundle {
+    pub fn health(&self) -> Health {
+        match self {
+            TowerBundle::Money(bundle) => bundle.health,
+            TowerBundle::SimpleProjectile(bundle) => bundle.health,
+        }
+    }
+}
+
+#[derive(Hash,PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Tower {
     Money,
     NormalProjectile,
+    SplashProjectile,
+    PiercingProjectile,
+    AntiAir,
+    BackwardsProjectile,
 }
 
 #[derive(Clone)]
@@ -82,7 +134,7 @@ lazy_static! {
                     money: 50, 
                     gaintimer: Timer::from_seconds(15.0, TimerMode::Repeating),
                 },
-                health: Health { health: 50 },
+                health: Health { health: 50, max: 50 },
                 }
             ), "art/pig.png".to_string())
         );
@@ -92,15 +144,116 @@ lazy_static! {
                 projectile_tower: ProjectileTower {
                     speed: 200.0,
                     damage: 20,
+                    damage_type: DamageType::Normal,
+                    anti_air: false,
+                    direction: 1.0,
                     shoot_timer: Timer::from_seconds(5.0, TimerMode::Repeating), 
                 },
-                health: Health { health: 50 },
-            }), "art/yeti.png".to_string())
+                health: Health { health: 50, max: 50 },
+            }), "kenney/PNG/DefaultSize/towerDefense_tile203.png".to_string())
+        );
+        map.insert(
+            Tower::SplashProjectile,
+            (TowerBundle::SimpleProjectile( SimpleProjectileBundle {
+                projectile_tower: ProjectileTower {
+                    speed: 150.0,
+                    damage: 15,
+                    damage_type: DamageType::Splash,
+                    anti_air: false,
+                    direction: 1.0,
+                    shoot_timer: Timer::from_seconds(6.0, TimerMode::Repeating),
+                },
+                health: Health { health: 50, max: 50 },
+            }), "kenney/PNG/DefaultSize/towerDefense_tile250.png".to_string())
+        );
+        map.insert(
+            Tower::PiercingProjectile,
+            (TowerBundle::SimpleProjectile( SimpleProjectileBundle {
+                projectile_tower: ProjectileTower {
+                    speed: 250.0,
+                    damage: 15,
+                    damage_type: DamageType::Piercing,
+                    anti_air: false,
+                    direction: 1.0,
+                    shoot_timer: Timer::from_seconds(4.0, TimerMode::Repeating),
+                },
+                health: Health { health: 50, max: 50 },
+            }), "kenney/PNG/DefaultSize/towerDefense_tile249.png".to_string())
+        );
+        map.insert(
+            Tower::AntiAir,
+            (TowerBundle::SimpleProjectile( SimpleProjectileBundle {
+                projectile_tower: ProjectileTower {
+                    speed: 300.0,
+                    damage: 25,
+                    damage_type: DamageType::Normal,
+                    anti_air: true,
+                    direction: 1.0,
+                    shoot_timer: Timer::from_seconds(3.0, TimerMode::Repeating),
+                },
+                health: Health { health: 50, max: 50 },
+            }), "kenney/PNG/DefaultSize/towerDefense_tile206.png".to_string())
+        );
+        map.insert(
+            Tower::BackwardsProjectile,
+            (TowerBundle::SimpleProjectile( SimpleProjectileBundle {
+                projectile_tower: ProjectileTower {
+                    speed: 200.0,
+                    damage: 20,
+                    damage_type: DamageType::Normal,
+                    anti_air: false,
+                    direction: -1.0,
+                    shoot_timer: Timer::from_seconds(4.0, TimerMode::Repeating),
+                },
+                health: Health { health: 50, max: 50 },
+            }), "kenney/PNG/DefaultSize/towerDefense_tile226.png".to_string())
         );
         map
     };
 }
 
+fn tower_animation(tile: usize, fps: f32) -> SpriteAnimation {
+    let mut clips = HashMap::new();
+    clips.insert(AnimationClip::Idle, Clip { frames: vec![tile], looping: true });
+    clips.insert(AnimationClip::Attack, Clip {
+        frames: vec![tile, animation::FIRE_FRAMES[0], animation::FIRE_FRAMES[1], tile],
+        looping: false,
+    });
+    SpriteAnimation::new(clips, fps, AnimationClip::Idle)
+}
+
+lazy_static! {
+    /// Projectile towers, drawn from the Kenney tilesheet so each has an attack clip. The Money
+    /// tower animates from its own sheet instead.
+    static ref TOWER_ANIMATIONS: HashMap<Tower, SpriteAnimation> = {
+        let mut map = HashMap::new();
+        map.insert(Tower::NormalProjectile, tower_animation(203, 8.0));
+        map.insert(Tower::BackwardsProjectile, tower_animation(226, 8.0));
+        map.insert(Tower::SplashProjectile, tower_animation(250, 8.0));
+        map.insert(Tower::PiercingProjectile, tower_animation(249, 8.0));
+        map.insert(Tower::AntiAir, tower_animation(206, 8.0));
+        map
+    };
+}
+
+/// Generated from the clips in the same folder by `scripts/pig_sheet.sh`.
+const PIG_SHEET: &str = "art/PigAnimations/pig_sheet.png";
+
+/// The Money tower's Woobling/DerpyCoin sheet, if `pack_frames` has generated it.
+#[derive(Resource)]
+pub struct MoneyTowerAnimation(pub Option<(Handle<TextureAtlas>, SpriteAnimation)>);
+
+/// A tower standing on the board, as opposed to the placement preview.
+#[derive(Component, Clone, Copy)]
+pub struct PlacedTower {
+    pub id: TowerId,
+    pub tower: Tower,
+}
+
+/// Marks a projectile with the tower that fired it.
+#[derive(Component, Clone, Copy)]
+pub struct FiredBy(pub TowerId);
+
 #[derive(Component)]
 pub struct Preview(Tower); 
 
@@ -110,22 +263,45 @@ impl Plugin for TowerPlugin {
     fn build(&self, app: &mut App) {
         app 
             .insert_resource(SelectableTowers {
-                possible_towers: setup_tower_costs(),
+                // Filled from the meta save's starting loadout when a run starts.
+                possible_towers: Vec::new(),
                 selected_tower: None,
             })
             .add_event::<MoneyUpdated>()
+            .add_event::<ProjectileHit>()
             .add_systems(Startup, setup)
-            .add_systems(Update, (
-                spawn_tower_at_mouse, update_money, select_tower, projectile_damage_enemies,
-                shoot_projectiles, move_projectiles, despawn_out_of_bound_projectile, check_tower_health))
+            // Skipped when running headless, e.g. in the balance simulator.
+            .add_systems(Update, (spawn_tower_at_mouse, queue_tower_selection)
+                .run_if(in_state(GameState::Battle).and_then(any_with_component::<PrimaryWindow>())))
+            .add_systems(FixedUpdate, (select_towers, place_towers).chain()
+                .in_set(ActionSet::Handle)
+                .run_if(in_state(GameState::Battle)))
+            .add_systems(FixedUpdate, (
+                (update_money, shoot_projectiles).chain().in_set(GameplaySet::Act),
+                (move_projectiles, despawn_out_of_bound_projectile).chain().in_set(GameplaySet::Move),
+                projectile_damage_enemies.in_set(GameplaySet::Combat),
+                check_tower_health.in_set(GameplaySet::Resolve))
+                .run_if(in_state(GameState::Battle)))
         ;
     }
 }
 
 fn setup(
     mut commands: Commands,
-    asset_server: Res<AssetServer>
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
 ) {
+    let money_animation = animation::load_sheet_animation(
+        &asset_server,
+        &mut atlases,
+        PIG_SHEET,
+        &[("Woobling", AnimationClip::Idle, true), ("DerpyCoin", AnimationClip::Payout, false)],
+        AnimationClip::Idle,
+    );
+    if money_animation.is_none() {
+        warn!("{PIG_SHEET} is missing, run scripts/pig_sheet.sh to animate the Money tower");
+    }
+    commands.insert_resource(MoneyTowerAnimation(money_animation));
     spawn_preview(commands, &asset_server, Tower::Money);
 }
 
@@ -151,37 +327,66 @@ fn setup_tower_costs() -> Vec<BuyableTower> {
             cost: 200,
             tower_type: Tower::NormalProjectile
         },
+        BuyableTower {
+            cost: 300,
+            tower_type: Tower::SplashProjectile
+        },
+        BuyableTower {
+            cost: 250,
+            tower_type: Tower::PiercingProjectile
+        },
+        BuyableTower {
+            cost: 175,
+            tower_type: Tower::AntiAir
+        },
+        BuyableTower {
+            cost: 150,
+            tower_type: Tower::BackwardsProjectile
+        },
 
     ]
 }
 
-fn select_tower(
+pub fn buyable_tower(tower: Tower) -> BuyableTower {
+    setup_tower_costs().into_iter()
+        .find(|buyable| buyable.tower_type == tower)
+        .expect("every tower has a cost")
+}
+
+const TOWER_KEYS: [KeyCode; 6] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5, KeyCode::Key6];
+
+fn queue_tower_selection(
     input: Res<Input<KeyCode>>,
-    mut selectable_towers: ResMut<SelectableTowers>
+    mut actions: ResMut<ActionQueue>,
 ) {
-    let towers_length = selectable_towers.possible_towers.len();
-    if input.just_pressed(KeyCode::Key1) && towers_length > 0 {
-        selectable_towers.selected_tower = Some(selectable_towers.possible_towers[0].clone());
-    } else if input.just_pressed(KeyCode::Key2) && towers_length > 1 {
-        selectable_towers.selected_tower = Some(selectable_towers.possible_towers[1].clone());
-    } else if input.just_pressed(KeyCode::Key3) && towers_length > 2 {
-        selectable_towers.selected_tower = Some(selectable_towers.possible_towers[2].clone());
-    } else if input.just_pressed(KeyCode::Key4) && towers_length > 3 {
-        selectable_towers.selected_tower = Some(selectable_towers.possible_towers[3].clone());
-    } else if input.just_pressed(KeyCode::Key5) && towers_length > 4 {
-        selectable_towers.selected_tower = Some(selectable_towers.possible_towers[4].clone());
+    if let Some(index) = TOWER_KEYS.iter().position(|key| input.just_pressed(*key)) {
+        actions.push(PlayerAction::SelectTower(index));
     }
+}
 
+fn select_towers(
+    mut actions: EventReader<PlayerAction>,
+    mut selectable_towers: ResMut<SelectableTowers>
+) {
+    for action in actions.iter() {
+        if let PlayerAction::SelectTower(index) = *action {
+            if let Some(tower) = selectable_towers.possible_towers.get(index).cloned() {
+                selectable_towers.selected_tower = Some(tower);
+            }
+        }
+    }
 }
 
 fn spawn_tower_at_mouse(
     mut commands: Commands, 
     asset_server: Res<AssetServer>, 
     input: Res<Input<MouseButton>>,
-    mut board: ResMut<Board>,
-    mut money: ResMut<Money>,
-    mut money_updated: EventWriter<MoneyUpdated>,
+    mut actions: ResMut<ActionQueue>,
+    board: Res<Board>,
+    money: Res<Money>,
     selected_tower: Res<SelectableTowers>,
+    run: Res<Run>,
+    speed: Res<GameSpeed>,
     primary_query: Query<&Window, With<PrimaryWindow>>,
     camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     mut preview_q: Query<(&mut Transform, &mut Sprite, Entity, &Preview)>
@@ -189,6 +394,9 @@ fn spawn_tower_at_mouse(
 
     let (mut transform, mut sprite, preview_ent, preview) = preview_q.single_mut();
     sprite.color.set_a(0.0);
+    if speed.paused {
+        return;
+    }
     if let Some(tower_cost) = &selected_tower.selected_tower {
 
         let (camera, camera_transform) = camera_q.single();
@@ -208,19 +416,13 @@ fn spawn_tower_at_mouse(
             if board.towers[&grid_pos] != None {
                 return;
             }
-            else if tower_cost.cost > money.0 {
+            let cost = modifiers::modify_u32(&run, Stat::TowerCost(tower_cost.tower_type), tower_cost.cost);
+            if cost > money.0 {
                 return;
             }
             let spawn_pos = convert_grid_to_world(grid_pos);
             if input.just_pressed(MouseButton::Left) {
-                money.0 -= tower_cost.cost;
-                money_updated.send(MoneyUpdated{
-                    new_value: money.0
-                });
-
-
-                let ent = spawn_tower(commands, spawn_pos, tower_cost, &asset_server);
-                board.towers.insert(grid_pos, Some(ent));
+                actions.push(PlayerAction::PlaceTower { cell: grid_pos });
             } else {
                 if tower_cost.tower_type != preview.0 {
                     commands.entity(preview_ent).despawn();
@@ -236,23 +438,110 @@ fn spawn_tower_at_mouse(
     }
 }
 
-fn spawn_tower(mut commands: Commands<'_, '_>, spawn_pos: Vec3, tower_cost: &BuyableTower, asset_server: &Res<AssetServer>) -> Entity {
+/// Buys and places the selected tower. Checks again, since the board may have changed since the click was queued.
+fn place_towers(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    asset_server: Res<AssetServer>,
+    tilesheet: Res<Tilesheet>,
+    money_tower_animation: Res<MoneyTowerAnimation>,
+    mut board: ResMut<Board>,
+    mut money: ResMut<Money>,
+    mut money_updated: EventWriter<MoneyUpdated>,
+    selected_tower: Res<SelectableTowers>,
+    run: Res<Run>,
+) {
+    for action in actions.iter() {
+        let PlayerAction::PlaceTower { cell } = *action else {
+            continue;
+        };
+        let Some(tower_cost) = &selected_tower.selected_tower else {
+            continue;
+        };
+        if board.towers.get(&cell) != Some(&None) {
+            continue;
+        }
+        let cost = modifiers::modify_u32(&run, Stat::TowerCost(tower_cost.tower_type), tower_cost.cost);
+        if cost > money.0 {
+            continue;
+        }
+        money.0 -= cost;
+        money_updated.send(MoneyUpdated{
+            new_value: money.0
+        });
+
+        let id = board.allocate_tower_id();
+        let ent = spawn_tower(&mut commands, convert_grid_to_world(cell), tower_cost, &asset_server, &tilesheet, &money_tower_animation);
+        // Health is the one stat kept on the tower, so its modifiers apply once, at placement.
+        let mut health = TOWER_IMPLEMENTATIONS[&tower_cost.tower_type].0.health();
+        health.max = modifiers::modify_i32(&run, Stat::TowerHealth, health.max);
+        health.health = health.max;
+        commands.entity(ent).insert((PlacedTower { id, tower: tower_cost.tower_type }, health));
+        board.towers.insert(cell, Some(id));
+    }
+}
+
+pub fn spawn_tower(
+    commands: &mut Commands, spawn_pos: Vec3, tower_cost: &BuyableTower, asset_server: &Res<AssetServer>,
+    tilesheet: &Res<Tilesheet>, money_tower_animation: &Res<MoneyTowerAnimation>
+) -> Entity {
     let texture_path = &TOWER_IMPLEMENTATIONS[&tower_cost.tower_type].1;
     let ent = match &TOWER_IMPLEMENTATIONS[&tower_cost.tower_type].0 {
-        TowerBundle::Money(money_tower) => 
-            commands.spawn((
-                get_sprite_bundle(&texture_path, spawn_pos, asset_server, 1.0),
-                money_tower.clone()
-            )),
-        TowerBundle::SimpleProjectile(projectile_tower) => 
-            commands.spawn((
-                get_sprite_bundle(&texture_path, spawn_pos, asset_server, 1.0),
-                projectile_tower.clone()
-            )),
+        TowerBundle::Money(money_tower) => {
+            if let Some((atlas, animation)) = &money_tower_animation.0 {
+                let mut sprite_sheet_bundle = get_sprite_sheet_bundle(animation.current_index(), spawn_pos, tilesheet);
+                sprite_sheet_bundle.texture_atlas = atlas.clone();
+                commands.spawn((
+                    sprite_sheet_bundle,
+                    animation.clone(),
+                    money_tower.clone()
+                ))
+            } else {
+                commands.spawn((
+                    get_sprite_bundle(&texture_path, spawn_pos, asset_server, 1.0),
+                    money_tower.clone()
+                ))
+            }
+        },
+        TowerBundle::SimpleProjectile(projectile_tower) => {
+            let flip_x = projectile_tower.projectile_tower.direction < 0.0;
+            if let Some(animation) = TOWER_ANIMATIONS.get(&tower_cost.tower_type) {
+                let mut sprite_sheet_bundle = get_sprite_sheet_bundle(animation.current_index(), spawn_pos, tilesheet);
+                sprite_sheet_bundle.sprite.flip_x = flip_x;
+                commands.spawn((
+                    sprite_sheet_bundle,
+                    animation.clone(),
+                    projectile_tower.clone()
+                ))
+            } else {
+                let mut sprite_bundle = get_sprite_bundle(&texture_path, spawn_pos, asset_server, 1.0);
+                sprite_bundle.sprite.flip_x = flip_x;
+                commands.spawn((
+                    sprite_bundle,
+                    projectile_tower.clone()
+                ))
+            }
+        },
     };
     ent.id()
 }
 
+fn get_sprite_sheet_bundle(index: usize, spawn_pos: Vec3, tilesheet: &Res<Tilesheet>) -> SpriteSheetBundle {
+    return SpriteSheetBundle {
+        texture_atlas: tilesheet.0.clone(),
+        transform: Transform {
+            translation: spawn_pos,
+            ..Default::default()
+        },
+        sprite: TextureAtlasSprite {
+            index,
+            custom_size: Some(Vec2::new(200.0, 150.0)),
+            ..default()
+        },
+        ..default()
+    }
+}
+
 fn get_sprite_bundle(texture_path: &str, spawn_pos: Vec3, asset_server: &Res<AssetServer>, alpha: f32) -> SpriteBundle {
     return SpriteBundle {
         texture: asset_server.load(texture_path),
@@ -270,19 +559,21 @@ fn get_sprite_bundle(texture_path: &str, spawn_pos: Vec3, asset_server: &Res<Ass
 }
 
 fn update_money(
-    mut money: ResMut<Money>, 
-    time: Res<Time>,
-    mut towers: Query<&mut MoneyGain>,
-    mut money_updated: EventWriter<MoneyUpdated>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<FixedTime>,
+    run: Res<Run>,
+    mut towers: Query<(&mut MoneyGain, &Transform, Option<&mut SpriteAnimation>)>,
 ) {
-    for mut tower in &mut towers {
-        tower.gaintimer.tick(time.delta());
+    for (mut tower, transform, animation) in &mut towers {
+        tower.gaintimer.tick(time.period);
 
         if tower.gaintimer.finished() {
-            money.0 += tower.money;
-            money_updated.send(MoneyUpdated{
-                new_value: money.0
-            });
+            if let Some(mut animation) = animation {
+                animation.play(AnimationClip::Payout);
+            }
+            let payout = modifiers::modify_u32(&run, Stat::Payout, tower.money);
+            pickups::spawn_coin(&mut commands, &asset_server, payout, transform.translation);
         }
     }
 
@@ -290,39 +581,57 @@ fn update_money(
 
 fn shoot_projectiles(
     mut commands: Commands,
-    time: Res<Time>,
-    mut towers: Query<(&mut ProjectileTower, &Transform)>,
-    enemies: Query<(&EnemyHealth, &Transform)>,
-    asset_server: Res<AssetServer>
+    time: Res<FixedTime>,
+    mut towers: Query<(&mut ProjectileTower, &Transform, Option<&mut SpriteAnimation>, Option<&PlacedTower>)>,
+    enemies: Query<(&EnemyHealth, &Transform, Option<&Flying>), Without<Untargetable>>,
+    asset_server: Res<AssetServer>,
+    run: Res<Run>,
 ) {
-    for (mut tower, transform) in &mut towers {
-        tower.shoot_timer.tick(time.delta());
+    for (mut tower, transform, animation, placed) in &mut towers {
+        tower.shoot_timer.tick(time.period);
         let mut enemy_present = false;
-        for (_, enemy_transform) in &enemies {
-            if enemy_transform.translation.y == transform.translation.y {
+        for (_, enemy_transform, flying) in &enemies {
+            if flying.is_some() && !tower.anti_air {
+                continue;
+            }
+            let ahead = (enemy_transform.translation.x - transform.translation.x) * tower.direction > 0.0;
+            if enemy_transform.translation.y == transform.translation.y && ahead {
                enemy_present = true;
                break; 
             }
         }
         if tower.shoot_timer.finished() && enemy_present {
-            commands.spawn((
-                get_sprite_bundle(
-                    "kenney/PNG/DefaultSize/towerDefense_tile251.png", transform.translation, &asset_server, 1.0),
-                Projectile {
-                    speed: tower.speed,
-                    damage: tower.damage
-                }
-            ));
+            if let Some(mut animation) = animation {
+                animation.play(AnimationClip::Attack);
+            }
+            let projectile = spawn_projectile(&mut commands, &asset_server, transform.translation, Projectile {
+                speed: tower.speed,
+                damage: modifiers::modify_i32(&run, Stat::ProjectileDamage, tower.damage),
+                damage_type: tower.damage_type,
+                anti_air: tower.anti_air,
+                direction: tower.direction,
+            });
+            if let Some(placed) = placed {
+                commands.entity(projectile).insert(FiredBy(placed.id));
+            }
         }
     }
 }
 
+pub fn spawn_projectile(commands: &mut Commands, asset_server: &Res<AssetServer>, position: Vec3, projectile: Projectile) -> Entity {
+    commands.spawn((
+        get_sprite_bundle("kenney/PNG/DefaultSize/towerDefense_tile251.png", position, asset_server, 1.0),
+        projectile,
+        Interpolated::at(position),
+    )).id()
+}
+
 fn move_projectiles(
     mut projectiles: Query<(&mut Transform, &Projectile)>,
-    time: Res<Time>
+    time: Res<FixedTime>
 ) {
     for (mut transform, projectile) in projectiles.iter_mut() {
-        transform.translation.x += projectile.speed * time.delta_seconds();
+        transform.translation.x += projectile.speed * projectile.direction * time.period.as_secs_f32();
     }
 }
 
@@ -331,7 +640,7 @@ fn despawn_out_of_bound_projectile(
     mut commands: Commands,
 ) {
     for (transform, _, projectile_ent) in projectiles.iter_mut() {
-        if transform.translation.x > 550.0 {
+        if transform.translation.x > 550.0 || transform.translation.x < -650.0 {
             commands.entity(projectile_ent).despawn();
         }
     }
@@ -339,27 +648,50 @@ fn despawn_out_of_bound_projectile(
 
 fn projectile_damage_enemies(
     mut commands: Commands,
-    mut projectiles: Query<(&Transform, &Projectile, Entity)>,
-    mut enemies: Query<(&mut EnemyHealth, &Transform)>
+    mut projectile_hit: EventWriter<ProjectileHit>,
+    projectiles: Query<(&Transform, &Projectile, Option<&FiredBy>, Entity)>,
+    mut enemies: Query<(&mut EnemyHealth, Option<&mut Armor>, &Transform, Option<&Flying>, Entity), Without<Untargetable>>
 ) {
-    for (transform, projectile, projectile_ent) in projectiles.iter_mut() {
-        for (mut enemy_health, enemy_transform ) in &mut enemies {
-            if transform.translation.y == enemy_transform.translation.y && (transform.translation.x - enemy_transform.translation.x).abs() < 10.0 {
-                enemy_health.health -= projectile.damage;
-                commands.entity(projectile_ent).despawn();
+    for (transform, projectile, fired_by, projectile_ent) in projectiles.iter() {
+        let hit = enemies.iter().any(|(_, _, enemy_transform, flying, _)| {
+            (flying.is_none() || projectile.anti_air)
+                && transform.translation.y == enemy_transform.translation.y && (transform.translation.x - enemy_transform.translation.x).abs() < 10.0
+        });
+        if !hit {
+            continue;
+        }
+        let radius = if projectile.damage_type == DamageType::Splash { SPLASH_RADIUS } else { 10.0 };
+        for (mut enemy_health, armor, enemy_transform, flying, enemy_ent) in &mut enemies {
+            if flying.is_some() && !projectile.anti_air {
+                continue;
+            }
+            if transform.translation.y == enemy_transform.translation.y && (transform.translation.x - enemy_transform.translation.x).abs() < radius {
+                let dealt = apply_damage(&mut enemy_health, armor, projectile.damage, projectile.damage_type);
+                projectile_hit.send(ProjectileHit {
+                    target: enemy_ent,
+                    damage: projectile.damage,
+                    dealt,
+                    position: enemy_transform.translation,
+                    source: fired_by.map(|fired_by| fired_by.0),
+                });
             }
         }
+        commands.entity(projectile_ent).despawn();
     } 
 }
 
 
 fn check_tower_health(
     mut commands: Commands,
-    query: Query<(&Health, Entity), Changed<Health>>,
+    mut board: ResMut<Board>,
+    query: Query<(&Health, Option<&PlacedTower>, Entity), Changed<Health>>,
 ) {
-    for (health, entity) in query.iter() {
+    for (health, placed, entity) in query.iter() {
         if health.health <= 0 {
-            commands.entity(entity).despawn();
+            if let Some(placed) = placed {
+                board.remove_tower(placed.id);
+            }
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
\ No newline at end of file
@@ -1,10 +1,47 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, sprite::Anchor};
 
-use crate::towers::MoneyUpdated;
+use crate::{enemies::{Boss, EnemyHealth}, run::Run, towers::{self, MoneyUpdated, ProjectileHit}};
+
+const HEALTH_BAR_SIZE: Vec2 = Vec2::new(50.0, 6.0);
+const ENEMY_HEALTH_BAR_OFFSET: f32 = 40.0;
+const TOWER_HEALTH_BAR_OFFSET: f32 = 50.0;
+// Sprite colors only multiply the texture, which can't brighten dark pixels, so the flash is
+// a white quad drawn over the entity instead.
+const FLASH_SIZE: Vec2 = Vec2::new(64.0, 64.0);
+const FLASH_ALPHA: f32 = 0.7;
 
 #[derive(Component)]
 pub struct MoneyText;
 
+#[derive(Component)]
+pub struct BossHealthBar;
+
+#[derive(Component)]
+pub struct BossHealthFill;
+
+#[derive(Component)]
+pub struct HealthBar {
+    pub offset: f32,
+}
+
+#[derive(Component)]
+pub struct HealthBarFill;
+
+#[derive(Component)]
+pub struct DamageFlash {
+    pub timer: Timer,
+}
+
+/// White quad child that fades out over the `DamageFlash` timer.
+#[derive(Component)]
+pub struct FlashOverlay;
+
+#[derive(Component)]
+pub struct FloatingText {
+    pub timer: Timer,
+    pub speed: f32,
+}
+
 pub fn spawn_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
     
     commands.spawn((
@@ -29,6 +66,95 @@ pub fn spawn_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
 }
 
+/// Row along the bottom of the screen listing the run's relics.
+#[derive(Component)]
+pub struct RelicStrip;
+
+pub fn spawn_relic_strip(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                left: Val::Px(10.0),
+                column_gap: Val::Px(12.0),
+                ..default()
+            },
+            z_index: ZIndex::Global(5),
+            ..default()
+        },
+        RelicStrip,
+    ));
+}
+
+pub fn update_relic_strip(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    run: Res<Run>,
+    strip: Query<Entity, With<RelicStrip>>,
+) {
+    if !run.is_changed() {
+        return;
+    }
+    for strip in &strip {
+        commands.entity(strip).despawn_descendants().with_children(|parent| {
+            for relic in &run.relics {
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(6.0)),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..default()
+                }).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        format!("{}: {}", relic.name(), relic.description()),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 20.0,
+                            color: Color::GOLD,
+                        },
+                    ));
+                });
+            }
+        });
+    }
+}
+
+/// Shown once a lane is breached a second time.
+pub fn spawn_game_over(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_end_screen(&mut commands, &asset_server, "GAME OVER", Color::RED);
+}
+
+/// Shown once the boss node is cleared.
+pub fn spawn_victory(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_end_screen(&mut commands, &asset_server, "VICTORY", Color::GOLD);
+}
+
+fn spawn_end_screen(commands: &mut Commands, asset_server: &Res<AssetServer>, message: &str, color: Color) {
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+        z_index: ZIndex::Global(10),
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            message,
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 120.0,
+                color,
+            },
+        ));
+    });
+}
+
 pub fn update_money(
     mut money_updated: EventReader<MoneyUpdated>,
     mut query: Query<&mut Text, With<MoneyText>>,
@@ -39,4 +165,242 @@ pub fn update_money(
             text.sections[1].value = format!("{val}");
         }
     }
+}
+
+pub fn spawn_floating_text(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    value: String,
+    position: Vec3,
+    color: Color,
+) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                value,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color,
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(position.x, position.y, 10.0)),
+            ..default()
+        },
+        FloatingText {
+            timer: Timer::from_seconds(1.0, TimerMode::Once),
+            speed: 40.0,
+        },
+    ));
+}
+
+pub fn animate_floating_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(&mut Transform, &mut Text, &mut FloatingText, Entity)>,
+) {
+    for (mut transform, mut text, mut floating, entity) in &mut query {
+        floating.timer.tick(time.delta());
+        transform.translation.y += floating.speed * time.delta_seconds();
+        let alpha = floating.timer.percent_left();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
+        }
+        if floating.timer.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Shows a bar across the top of the screen while any boss is alive.
+pub fn update_boss_health_bar(
+    mut commands: Commands,
+    bosses: Query<&EnemyHealth, With<Boss>>,
+    bars: Query<Entity, With<BossHealthBar>>,
+    mut fills: Query<&mut Style, With<BossHealthFill>>,
+) {
+    if bosses.is_empty() {
+        for bar in &bars {
+            commands.entity(bar).despawn_recursive();
+        }
+        return;
+    }
+    let health: i32 = bosses.iter().map(|boss| boss.health.max(0)).sum();
+    let max: i32 = bosses.iter().map(|boss| boss.max).sum();
+    let percent = 100.0 * health as f32 / max as f32;
+    if bars.is_empty() {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(20.0),
+                    left: Val::Percent(25.0),
+                    width: Val::Percent(50.0),
+                    height: Val::Px(24.0),
+                    ..default()
+                },
+                background_color: Color::rgb(0.2, 0.2, 0.2).into(),
+                ..default()
+            },
+            BossHealthBar,
+        )).with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(percent),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                },
+                BossHealthFill,
+            ));
+        });
+    }
+    for mut style in &mut fills {
+        style.width = Val::Percent(percent);
+    }
+}
+
+/// Keeps the bar upright above the parent, undoing any flip or rotation on the parent sprite.
+fn health_bar_transform(parent: &Transform, offset: f32) -> Transform {
+    let parent_matrix = Mat4::from_scale_rotation_translation(parent.scale, parent.rotation, Vec3::ZERO);
+    let local = parent_matrix.inverse() * Mat4::from_translation(Vec3::new(0.0, offset, 1.0));
+    Transform::from_matrix(local)
+}
+
+fn spawn_health_bar(commands: &mut Commands, parent: Entity, parent_transform: &Transform, offset: f32) {
+    let bar = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.2, 0.2, 0.2),
+                custom_size: Some(HEALTH_BAR_SIZE),
+                ..default()
+            },
+            transform: health_bar_transform(parent_transform, offset),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        HealthBar { offset },
+    )).with_children(|bar| {
+        bar.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::LIME_GREEN,
+                    custom_size: Some(HEALTH_BAR_SIZE),
+                    anchor: Anchor::CenterLeft,
+                    ..default()
+                },
+                transform: Transform::from_xyz(-HEALTH_BAR_SIZE.x / 2.0, 0.0, 0.1),
+                ..default()
+            },
+            HealthBarFill,
+        ));
+    }).id();
+    let overlay = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+                custom_size: Some(FLASH_SIZE),
+                ..default()
+            },
+            transform: health_bar_transform(parent_transform, 0.0),
+            ..default()
+        },
+        FlashOverlay,
+    )).id();
+    // Start finished so nothing flashes until the first hit.
+    let mut timer = Timer::from_seconds(0.1, TimerMode::Once);
+    timer.tick(timer.duration());
+    commands.entity(parent)
+        .push_children(&[bar, overlay])
+        .insert(DamageFlash { timer });
+}
+
+pub fn attach_health_bars(
+    mut commands: Commands,
+    enemies: Query<(Entity, &Transform), Added<EnemyHealth>>,
+    towers: Query<(Entity, &Transform), Added<towers::Health>>,
+) {
+    for (entity, transform) in &enemies {
+        spawn_health_bar(&mut commands, entity, transform, ENEMY_HEALTH_BAR_OFFSET);
+    }
+    for (entity, transform) in &towers {
+        spawn_health_bar(&mut commands, entity, transform, TOWER_HEALTH_BAR_OFFSET);
+    }
+}
+
+fn set_health_bar(
+    fraction: f32,
+    parent_transform: &Transform,
+    children: &Children,
+    bars: &mut Query<(&HealthBar, &mut Transform, &mut Visibility, &Children), (Without<HealthBarFill>, Without<EnemyHealth>, Without<towers::Health>)>,
+    fills: &mut Query<&mut Sprite, With<HealthBarFill>>,
+) {
+    for child in children.iter() {
+        let Ok((bar, mut transform, mut visibility, bar_children)) = bars.get_mut(*child) else {
+            continue;
+        };
+        *transform = health_bar_transform(parent_transform, bar.offset);
+        *visibility = if fraction < 1.0 { Visibility::Inherited } else { Visibility::Hidden };
+        for fill_child in bar_children.iter() {
+            if let Ok(mut fill) = fills.get_mut(*fill_child) {
+                fill.custom_size = Some(Vec2::new(HEALTH_BAR_SIZE.x * fraction.clamp(0.0, 1.0), HEALTH_BAR_SIZE.y));
+            }
+        }
+    }
+}
+
+pub fn update_health_bars(
+    enemies: Query<(&EnemyHealth, &Transform, &Children), Changed<EnemyHealth>>,
+    towers: Query<(&towers::Health, &Transform, &Children), Changed<towers::Health>>,
+    mut bars: Query<(&HealthBar, &mut Transform, &mut Visibility, &Children), (Without<HealthBarFill>, Without<EnemyHealth>, Without<towers::Health>)>,
+    mut fills: Query<&mut Sprite, With<HealthBarFill>>,
+) {
+    for (health, transform, children) in &enemies {
+        set_health_bar(health.health as f32 / health.max as f32, transform, children, &mut bars, &mut fills);
+    }
+    for (health, transform, children) in &towers {
+        set_health_bar(health.health as f32 / health.max as f32, transform, children, &mut bars, &mut fills);
+    }
+}
+
+/// Restarts the flash whenever an entity's health drops.
+pub fn trigger_damage_flash(
+    mut enemies: Query<&mut DamageFlash, (Changed<EnemyHealth>, Without<towers::Health>)>,
+    mut towers: Query<&mut DamageFlash, (Changed<towers::Health>, Without<EnemyHealth>)>,
+) {
+    for mut flash in enemies.iter_mut().chain(towers.iter_mut()) {
+        flash.timer.reset();
+    }
+}
+
+pub fn update_damage_flash(
+    time: Res<Time>,
+    mut flashes: Query<(&mut DamageFlash, &Children)>,
+    mut overlays: Query<&mut Sprite, With<FlashOverlay>>,
+) {
+    for (mut flash, children) in &mut flashes {
+        if flash.timer.finished() {
+            continue;
+        }
+        flash.timer.tick(time.delta());
+        let alpha = if flash.timer.finished() { 0.0 } else { FLASH_ALPHA * flash.timer.percent_left() };
+        for child in children.iter() {
+            if let Ok(mut overlay) = overlays.get_mut(*child) {
+                overlay.color.set_a(alpha);
+            }
+        }
+    }
+}
+
+pub fn spawn_damage_numbers(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut projectile_hit: EventReader<ProjectileHit>,
+) {
+    for hit in projectile_hit.iter() {
+        spawn_floating_text(&mut commands, &asset_server, format!("-{}", hit.damage), hit.position, Color::WHITE);
+    }
 }
\ No newline at end of file